@@ -0,0 +1,158 @@
+// Run with `cargo bench`, compare against a saved run with
+//
+//   cargo bench --bench ecs -- --save-baseline before
+//   cargo bench --bench ecs -- --baseline before
+//
+// Baselines stay local under target/criterion, timings only compare on the same machine.
+//
+use criterion::{ criterion_group, criterion_main, black_box, BenchmarkId, Criterion, Throughput };
+use simple_ecs::*;
+
+const COMPONENTS: [&str; 16] = [
+    "c0", "c1", "c2", "c3", "c4", "c5", "c6", "c7",
+    "c8", "c9", "c10", "c11", "c12", "c13", "c14", "c15",
+];
+
+#[derive(Default, Debug)]
+struct Cell {
+    calls: u64,
+}
+
+struct Components(Vec<&'static str>);
+
+impl<'a> Factory<'a, Cell> for Components {
+    fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
+        for component in &self.0 {
+            tools.add_component(component);
+        }
+    }
+
+    fn components(&self) -> Vec<NameTag> {
+        self.0.iter().map(|c| NameTag::from_str(c)).collect()
+    }
+}
+
+// `systems` systems that each update every object holding their component
+fn world(size: usize, systems: usize, names: Option<NamePolicy>) -> Ecs<'static, Cell> {
+    let mut builder = EcsBuilder::new(size);
+    if let Some(policy) = names {
+        builder = builder.with_name_index(policy);
+    }
+    for component in &COMPONENTS {
+        builder = builder.define_component(component);
+    }
+    let mut systems_builder = builder.build_systems::<Cell>();
+    for component in &COMPONENTS[..systems] {
+        systems_builder = systems_builder.define_system_fn(&[component], |target, objects, _| {
+            objects.get_mut(target).calls += 1;
+        });
+    }
+    systems_builder
+        .setup_factories()
+            .define_factory("all", Box::new(Components(COMPONENTS.to_vec())))
+            .define_factory("first", Box::new(Components(vec!["c0"])))
+            .define_factory("other", Box::new(Components(vec!["c1"])))
+        .finalize()
+}
+
+
+fn spawn_destroy(c: &mut Criterion) {
+    let mut group = c.benchmark_group("spawn_destroy");
+    for &count in &[100, 1_000, 10_000] {
+        group.throughput(Throughput::Elements(count as u64));
+        group.bench_with_input(BenchmarkId::new("batch", count), &count, |b, &count| {
+            let mut ecs = world(count, 0, None);
+            b.iter(|| {
+                let spawned = ecs.spawn_batch("all", count);
+                ecs.destroy_batch(&spawned);
+            });
+        });
+        group.bench_with_input(BenchmarkId::new("single", count), &count, |b, &count| {
+            let mut ecs = world(count, 0, None);
+            let mut spawned = Vec::with_capacity(count);
+            b.iter(|| {
+                for _ in 0..count {
+                    spawned.push(ecs.spawn("", "all").unwrap());
+                }
+                for target in spawned.drain(..) {
+                    ecs.destroy(&target);
+                }
+            });
+        });
+    }
+    group.finish();
+}
+
+// N systems × M entities, every entity matches every system
+fn update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("update");
+    for &systems in &[1, 4, 16] {
+        for &entities in &[100, 1_000, 10_000] {
+            let mut ecs = world(entities, systems, None);
+            ecs.spawn_batch("all", entities);
+            ecs.start();
+
+            group.throughput(Throughput::Elements((systems * entities) as u64));
+            group.bench_function(BenchmarkId::new(format!("{}_systems", systems), entities), |b| {
+                b.iter(|| ecs.update());
+            });
+        }
+    }
+    group.finish();
+}
+
+// one system over 10 000 entities of which only a share holds its component
+fn matches(c: &mut Criterion) {
+    let mut group = c.benchmark_group("matches");
+    let entities = 10_000;
+    group.throughput(Throughput::Elements(entities as u64));
+    for &percent in &[1, 10, 50, 100] {
+        let mut ecs = world(entities, 1, None);
+        let matching = entities * percent / 100;
+        ecs.spawn_batch("first", matching);
+        ecs.spawn_batch("other", entities - matching);
+        ecs.start();
+
+        group.bench_function(BenchmarkId::new("percent", percent), |b| {
+            b.iter(|| ecs.update());
+        });
+    }
+    group.finish();
+}
+
+fn find(c: &mut Criterion) {
+    let mut group = c.benchmark_group("find");
+    for &entities in &[100, 1_000, 10_000] {
+        for (label, names) in [("scan", None), ("indexed", Some(NamePolicy::Reject))].iter() {
+            let mut ecs = world(entities, 0, *names);
+            for i in 0..entities {
+                ecs.spawn(&format!("entity-{}", i), "all");
+            }
+            // the worst case for a scan
+            let last = format!("entity-{}", entities - 1);
+
+            group.bench_function(BenchmarkId::new(*label, entities), |b| {
+                b.iter(|| ecs.find(black_box(&last)));
+            });
+        }
+    }
+    group.finish();
+}
+
+fn open_update(c: &mut Criterion) {
+    let mut group = c.benchmark_group("open_update");
+    for &entities in &[100, 1_000, 10_000] {
+        let mut ecs = world(entities, 0, None);
+        ecs.spawn_batch("all", entities);
+        ecs.start();
+
+        group.throughput(Throughput::Elements(entities as u64));
+        group.bench_function(BenchmarkId::from_parameter(entities), |b| {
+            b.iter(|| ecs.open_update(|target, pool| pool[*target].calls += 1));
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, spawn_destroy, update, matches, find, open_update);
+criterion_main!(benches);
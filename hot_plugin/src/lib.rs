@@ -0,0 +1,32 @@
+use simple_ecs::*;
+
+// change and rebuild to see a running world pick it up
+const STEP: u64 = 1;
+
+#[derive(Default, Debug)]
+pub struct Cell {
+    pub moved: u64,
+    pub calls: u64, // calls made by the current behaviour instance
+}
+
+#[derive(Default)]
+pub struct Mover {
+    calls: u64,
+}
+
+impl Behaviour<Cell> for Mover {
+    fn required_components(&self) -> Vec<NameTag> {
+        vec![ NameTag::from_str("mover") ]
+    }
+
+    #[allow(unused_variables)]
+    fn on_update(&mut self, target: &ObjectIndex, objects: &mut Objects<Cell>, system: &mut System) {
+        self.calls += 1;
+        objects.get_mut(target).moved += STEP;
+        objects.get_mut(target).calls = self.calls;
+    }
+}
+
+export_behaviours!(Cell, |registrar| {
+    registrar.add("mover", Box::new(Mover::default()));
+});
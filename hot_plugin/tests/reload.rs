@@ -0,0 +1,67 @@
+use std::env::consts::{ DLL_PREFIX, DLL_SUFFIX };
+use std::path::PathBuf;
+
+use simple_ecs::*;
+use hot_plugin::Cell;
+
+// the cdylib sits next to the deps folder this test was built into
+fn plugin_path() -> PathBuf {
+    let exe = std::env::current_exe().unwrap();
+    let dir = exe.parent().and_then(|deps| deps.parent()).unwrap();
+    dir.join(format!("{}hot_plugin{}", DLL_PREFIX, DLL_SUFFIX))
+}
+
+struct Spawner;
+impl<'a> Factory<'a, Cell> for Spawner {
+    fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
+        tools.add_component("mover");
+    }
+
+    fn components(&self) -> Vec<NameTag> { vec![ NameTag::from_str("mover") ] }
+}
+
+#[test]
+fn reload_keeps_objects() {
+    let mut plugin = HotPlugin::<Cell>::load(plugin_path()).unwrap();
+    let mut ecs = EcsBuilder::new(10)
+        .define_component("mover")
+        .build_systems()
+            .define_hot_systems(&mut plugin)
+        .setup_factories()
+            .define_factory("cell", Box::new(Spawner))
+        .finalize();
+    assert_eq!(plugin.behaviour_names(), vec!["mover"]);
+
+    ecs.start();
+    ecs.spawn("a", "cell");
+    ecs.update();
+    ecs.update();
+    let a = ecs.find("a").unwrap();
+    assert_eq!(ecs.get_ref(&a).moved, 2);
+    assert_eq!(ecs.get_ref(&a).calls, 2);
+
+    // nothing changed on disk
+    assert_eq!(plugin.reload_if_changed(&mut ecs), Ok(false));
+
+    plugin.reload(&mut ecs).unwrap();
+    ecs.update();
+    assert_eq!(ecs.get_ref(&a).moved, 3);
+    assert_eq!(ecs.get_ref(&a).calls, 1); // a fresh behaviour took over
+}
+
+#[derive(Default, Debug)]
+struct OtherCell {
+    _value: [u64; 8],
+}
+
+#[test]
+fn load_errors() {
+    let missing = HotPlugin::<Cell>::load("does/not/exist.so");
+    assert!(matches!(missing, Err(PluginError::Io(_))));
+
+    let not_a_library = HotPlugin::<Cell>::load(concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml"));
+    assert!(matches!(not_a_library, Err(PluginError::Load(_))));
+
+    let wrong_object = HotPlugin::<OtherCell>::load(plugin_path());
+    assert!(matches!(wrong_object, Err(PluginError::ObjectMismatch { .. })));
+}
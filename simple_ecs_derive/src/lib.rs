@@ -0,0 +1,207 @@
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{ parse_macro_input, Data, DeriveInput, ImplItem, ItemImpl, LitStr, Path };
+
+
+// #[derive(Component)] implements simple_ecs::ComponentKey, the component name is
+// the snake cased type name unless given with #[component(name = "...")]
+#[proc_macro_derive(Component, attributes(component))]
+pub fn derive_component(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let ident = &input.ident;
+    let mut name = snake_case(&ident.to_string());
+
+    for attr in input.attrs.iter().filter(|a| a.path().is_ident("component")) {
+        let parsed = attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("name") {
+                name = meta.value()?.parse::<LitStr>()?.value();
+                Ok(())
+            } else {
+                Err(meta.error("expected `name = \"...\"`"))
+            }
+        });
+        if let Err(e) = parsed {
+            return e.to_compile_error().into();
+        }
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    quote! {
+        impl #impl_generics ::simple_ecs::ComponentKey for #ident #ty_generics #where_clause {
+            const NAME: &'static str = #name;
+        }
+    }.into()
+}
+
+
+// #[derive(Reflect)] exposes every field of a struct by name, fields marked with
+// #[reflect(skip)] are left out and don't need to implement Reflect themselves
+#[proc_macro_derive(Reflect, attributes(reflect))]
+pub fn derive_reflect(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    let ident = &input.ident;
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return syn::Error::new_spanned(ident, "Reflect can only be derived for structs").to_compile_error().into(),
+    };
+
+    let mut names: Vec<String> = Vec::new();
+    let mut members: Vec<syn::Member> = Vec::new();
+    for (i, field) in fields.iter().enumerate() {
+        let mut skip = false;
+        for attr in field.attrs.iter().filter(|a| a.path().is_ident("reflect")) {
+            let parsed = attr.parse_nested_meta(|meta| {
+                if meta.path.is_ident("skip") {
+                    skip = true;
+                    Ok(())
+                } else {
+                    Err(meta.error("expected `skip`"))
+                }
+            });
+            if let Err(e) = parsed {
+                return e.to_compile_error().into();
+            }
+        }
+        if skip {
+            continue;
+        }
+        match &field.ident {
+            Some(name) => {
+                names.push(name.to_string());
+                members.push(syn::Member::Named(name.clone()));
+            },
+            None => {
+                names.push(i.to_string());
+                members.push(syn::Member::Unnamed(syn::Index::from(i)));
+            },
+        }
+    }
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    quote! {
+        impl #impl_generics ::simple_ecs::Reflect for #ident #ty_generics #where_clause {
+            fn fields(&self) -> ::std::vec::Vec<::simple_ecs::FieldInfo> {
+                vec![ #( ::simple_ecs::FieldInfo {
+                    name: #names,
+                    type_name: ::simple_ecs::Reflect::type_name(&self.#members),
+                } ),* ]
+            }
+
+            fn field(&self, name: &str) -> ::std::option::Option<&dyn ::simple_ecs::Reflect> {
+                match name {
+                    #( #names => ::std::option::Option::Some(&self.#members), )*
+                    _ => ::std::option::Option::None,
+                }
+            }
+
+            fn field_mut(&mut self, name: &str) -> ::std::option::Option<&mut dyn ::simple_ecs::Reflect> {
+                match name {
+                    #( #names => ::std::option::Option::Some(&mut self.#members), )*
+                    _ => ::std::option::Option::None,
+                }
+            }
+        }
+    }.into()
+}
+
+
+// Placed on an `impl Behaviour<T> for ...` block, generates required_components
+// and access from the listed component keys:
+//
+//   #[system(reads(Position), writes(Velocity), without(Frozen), changed(Position))]
+//   impl Behaviour<Cell> for Mover {
+//       fn on_update(...) { ... }
+//   }
+//
+#[proc_macro_attribute]
+pub fn system(args: TokenStream, input: TokenStream) -> TokenStream {
+    let mut reads: Vec<Path> = Vec::new();
+    let mut writes: Vec<Path> = Vec::new();
+    let mut without: Vec<Path> = Vec::new();
+    let mut added: Vec<Path> = Vec::new();
+    let mut changed: Vec<Path> = Vec::new();
+    let mut removed: Vec<Path> = Vec::new();
+
+    let parser = syn::meta::parser(|meta| {
+        let list = if meta.path.is_ident("reads") {
+            &mut reads
+        } else if meta.path.is_ident("writes") {
+            &mut writes
+        } else if meta.path.is_ident("without") {
+            &mut without
+        } else if meta.path.is_ident("added") {
+            &mut added
+        } else if meta.path.is_ident("changed") {
+            &mut changed
+        } else if meta.path.is_ident("removed") {
+            &mut removed
+        } else {
+            return Err(meta.error("expected one of `reads`, `writes`, `without`, `added`, `changed` or `removed`"));
+        };
+        meta.parse_nested_meta(|key| {
+            list.push(key.path);
+            Ok(())
+        })
+    });
+    parse_macro_input!(args with parser);
+
+    let mut item = parse_macro_input!(input as ItemImpl);
+
+    for generated in ["required_components", "access"] {
+        let defined = item.items.iter().any(|i| matches!(i, ImplItem::Fn(f) if f.sig.ident == generated));
+        if defined {
+            let message = format!("`{}` is generated by #[system], remove it from the impl", generated);
+            return syn::Error::new_spanned(&item.self_ty, message).to_compile_error().into();
+        }
+    }
+
+    let required: Vec<&Path> = reads.iter().chain(writes.iter()).collect();
+    let reads = tags(reads.iter());
+    let writes = tags(writes.iter());
+    let without = tags(without.iter());
+    let added = tags(added.iter());
+    let changed = tags(changed.iter());
+    let removed = tags(removed.iter());
+    let required = tags(required.into_iter());
+
+    item.items.push(syn::parse_quote! {
+        fn required_components(&self) -> ::std::vec::Vec<::simple_ecs::NameTag> {
+            #required
+        }
+    });
+    item.items.push(syn::parse_quote! {
+        fn access(&self) -> ::simple_ecs::Access {
+            ::simple_ecs::Access {
+                reads: #reads,
+                writes: #writes,
+                without: #without,
+                added: #added,
+                changed: #changed,
+                removed: #removed,
+            }
+        }
+    });
+    quote!(#item).into()
+}
+
+fn tags<'a>(keys: impl Iterator<Item = &'a Path>) -> TokenStream2 {
+    let keys: Vec<&Path> = keys.collect();
+    quote! { vec![ #( ::simple_ecs::NameTag::of::<#keys>() ),* ] }
+}
+
+fn snake_case(name: &str) -> String {
+    let mut snake = String::new();
+    for (i, c) in name.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                snake.push('_');
+            }
+            snake.extend(c.to_lowercase());
+        } else {
+            snake.push(c);
+        }
+    }
+    snake
+}
@@ -0,0 +1,168 @@
+use std::fmt;
+use std::fmt::Write as _;
+use std::fmt::Debug;
+
+use super::json::quote;
+use super::{ ObjectIndex, SystemIndex, ComponentRefs, NameTag, System, Objects, Entities, CapacityReport };
+
+
+// Which objects a dump lists, systems only list the matches among them
+#[derive(Debug, Clone, PartialEq)]
+pub enum DumpFilter {
+    All,
+    Name(String),
+    Component(String), // objects having the component
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EntityDump {
+    pub index: ObjectIndex,
+    pub generation: u32, // times the slot was spawned into
+    pub name: String,
+    pub components: Vec<String>,
+    pub data: String, // Debug of the object
+    pub systems: Vec<SystemIndex>, // systems that update the object
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemDump {
+    pub system: SystemIndex,
+    pub label: String, // the components the system requires
+    pub without: Vec<String>,
+    pub matched: Vec<ObjectIndex>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct WorldDump {
+    pub tick: u64,
+    pub filter: DumpFilter,
+    pub entities: Vec<EntityDump>,
+    pub systems: Vec<SystemDump>,
+    pub capacity: CapacityReport,
+    pub next_free: Option<ObjectIndex>, // the slot the next spawn goes into, unless the world grows
+}
+
+impl WorldDump {
+    pub fn to_json(&self) -> String {
+        let c = &self.capacity;
+        let mut json = String::new();
+        let _ = write!(json, "{{\"tick\":{},\"filter\":{},", self.tick, match &self.filter {
+            DumpFilter::All => "null".to_string(),
+            DumpFilter::Name(name) => format!("{{\"name\":{}}}", quote(name)),
+            DumpFilter::Component(component) => format!("{{\"component\":{}}}", quote(component)),
+        });
+        let _ = write!(json, "\"free_list\":{{\"capacity\":{},\"active\":{},\"free\":{},\"high_water_mark\":{},\"times_grown\":{},\"next_free\":{}}},",
+            c.capacity, c.active, c.free, c.high_water_mark, c.times_grown,
+            self.next_free.map_or("null".to_string(), |n| n.to_string()));
+
+        json.push_str("\"entities\":[");
+        for (i, e) in self.entities.iter().enumerate() {
+            let _ = write!(json, "{}{{\"index\":{},\"generation\":{},\"name\":{},\"components\":{},\"systems\":{},\"data\":{}}}",
+                if i == 0 { "" } else { "," },
+                e.index, e.generation, quote(&e.name), strings(&e.components), numbers(&e.systems), quote(&e.data));
+        }
+        json.push_str("],\"systems\":[");
+        for (i, s) in self.systems.iter().enumerate() {
+            let _ = write!(json, "{}{{\"system\":{},\"label\":{},\"without\":{},\"matched\":{}}}",
+                if i == 0 { "" } else { "," },
+                s.system, quote(&s.label), strings(&s.without), numbers(&s.matched));
+        }
+        json.push_str("]}");
+        json
+    }
+}
+
+impl fmt::Display for WorldDump {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let c = &self.capacity;
+        writeln!(f, "world at tick {}", self.tick)?;
+        write!(f, "slots: {} capacity, {} active, {} free", c.capacity, c.active, c.free)?;
+        if let Some(next) = self.next_free {
+            write!(f, " (next {})", next)?;
+        }
+        writeln!(f, ", high water mark {}, grown {} times", c.high_water_mark, c.times_grown)?;
+
+        match &self.filter {
+            DumpFilter::All => writeln!(f, "entities: {}", self.entities.len())?,
+            DumpFilter::Name(name) => writeln!(f, "entities named '{}': {}", name, self.entities.len())?,
+            DumpFilter::Component(component) => writeln!(f, "entities with '{}': {}", component, self.entities.len())?,
+        }
+        for e in &self.entities {
+            writeln!(f, "  {} '{}' gen {} [{}] systems {:?}", e.index, e.name, e.generation, e.components.join(", "), e.systems)?;
+            writeln!(f, "      {}", e.data)?;
+        }
+
+        writeln!(f, "systems: {}", self.systems.len())?;
+        for s in &self.systems {
+            write!(f, "  {} {}", s.system, s.label)?;
+            if !s.without.is_empty() {
+                write!(f, " without ({})", s.without.join(", "))?;
+            }
+            writeln!(f, " matches {} {:?}", s.matched.len(), s.matched)?;
+        }
+        Ok(())
+    }
+}
+
+
+pub(crate) fn dump_world<T: Default + Debug>(
+    filter: DumpFilter,
+    objects: &Objects<T>,
+    entities: &Entities,
+    systems: &[System],
+    component_refs: &ComponentRefs,
+) -> WorldDump {
+    use DumpFilter::*;
+
+    let name_of = |pointer: ObjectIndex| objects.symbols.resolve(objects.active[entities.sparse[pointer]].1);
+    let component = match &filter {
+        Component(name) => component_refs.get(&NameTag::from_str(name)).map(|c| *c.index()),
+        _ => None,
+    };
+
+    let mut listed: Vec<ObjectIndex> = entities.active.iter()
+        .filter(|p| match &filter {
+            All => true,
+            Name(name) => name_of(**p) == name,
+            // unknown components match nothing
+            Component(_) => component.is_some_and(|c| entities.pool[**p].bit(c)),
+        })
+        .copied()
+        .collect();
+    listed.sort_unstable();
+
+    let updates = |system: &System, pointer: ObjectIndex|
+        system.updates_entities && system.matches(&entities.pool[pointer]);
+    let names = |flags| component_refs.decode(flags).iter().map(|c| c.name().to_string()).collect();
+
+    WorldDump {
+        tick: objects.tick,
+        entities: listed.iter().map(|p| EntityDump {
+            index: *p,
+            generation: entities.generations[*p],
+            name: name_of(*p).to_string(),
+            components: names(&entities.pool[*p]),
+            data: format!("{:?}", objects.pool[*p]),
+            systems: systems.iter().filter(|s| updates(s, *p)).map(|s| s.index).collect(),
+        }).collect(),
+        systems: systems.iter().map(|s| SystemDump {
+            system: s.index,
+            label: s.label(component_refs),
+            without: names(&s.excluded),
+            matched: listed.iter().filter(|p| updates(s, **p)).copied().collect(),
+        }).collect(),
+        capacity: entities.report(),
+        next_free: entities.free.last().copied(),
+        filter,
+    }
+}
+
+fn strings(list: &[String]) -> String {
+    let quoted: Vec<String> = list.iter().map(|s| quote(s)).collect();
+    format!("[{}]", quoted.join(","))
+}
+
+fn numbers(list: &[usize]) -> String {
+    let numbers: Vec<String> = list.iter().map(|n| n.to_string()).collect();
+    format!("[{}]", numbers.join(","))
+}
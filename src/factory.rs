@@ -1,9 +1,282 @@
+use std::fmt;
+use std::any::Any;
+use std::collections::HashMap;
 
-use super::{ NameTag, ComponentRefs, BitFlags };
+use super::{ SystemIndex, NameTag, ComponentRefs, ComponentKey, BitFlags, Symbol, Symbols };
 
 
 pub trait Factory<'a, T> {
     fn make_spawn(&mut self, tools: &mut BuildTools<T>);
+
+    // every component make_spawn may add, merged with those of the base factories when
+    // tooling lists a factory, so listing never has to run make_spawn. Debug builds check
+    // each spawn against it.
+    fn components(&self) -> Vec<NameTag>;
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FactoryError {
+    UnknownBase { factory: String, base: String },
+    Cycle(Vec<String>),
+    // a factory or system refers to a component that was not defined in the EcsBuilder
+    UnknownComponent { factory: String, component: String },
+    UnknownSystemComponent { system: SystemIndex, component: String },
+    UnknownSpatialComponent(String),
+}
+
+impl FactoryError {
+    // name of the factory the error was found on, None when it was found elsewhere
+    pub fn factory(&self) -> Option<&str> {
+        match self {
+            FactoryError::UnknownBase { factory, .. } => Some(factory),
+            FactoryError::Cycle(path) => Some(&path[0]),
+            FactoryError::UnknownComponent { factory, .. } => Some(factory),
+            FactoryError::UnknownSystemComponent { .. } => None,
+            FactoryError::UnknownSpatialComponent(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for FactoryError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FactoryError::UnknownBase { factory, base } =>
+                write!(f, "factory '{}' extends unknown factory '{}'", factory, base),
+            FactoryError::Cycle(path) =>
+                write!(f, "factory inheritance cycle: {}", path.join(" -> ")),
+            FactoryError::UnknownComponent { factory, component } =>
+                write!(f, "factory '{}' declares unknown component '{}'", factory, component),
+            FactoryError::UnknownSystemComponent { system, component } =>
+                write!(f, "system {} refers to unknown component '{}'", system, component),
+            FactoryError::UnknownSpatialComponent(component) =>
+                write!(f, "spatial index on unknown component '{}'", component),
+        }
+    }
+}
+
+impl std::error::Error for FactoryError {}
+
+
+pub(crate) struct FactoryEntry<'a, T> {
+    name: String,
+    symbol: Symbol,
+    base: Option<String>,
+    factory: Box<dyn Factory<'a, T>>,
+    chain: Vec<usize>, // resolved spawn order, base factories first and this one last
+}
+
+impl<'a, T> FactoryEntry<'a, T> {
+    pub(crate) fn new(name: &str, base: Option<&str>, factory: Box<dyn Factory<'a, T>>) -> Self {
+        FactoryEntry {
+            name: name.to_string(),
+            symbol: Symbol::EMPTY,
+            base: base.map(|b| b.to_string()),
+            factory,
+            chain: Vec::new(),
+        }
+    }
+}
+
+
+pub struct Factories<'a, T> {
+    entries: Vec<FactoryEntry<'a, T>>,
+    lookup: HashMap<Symbol, usize>, // type name -> entry index
+}
+
+impl<'a, T> Factories<'a, T> {
+
+    pub(crate) fn new() -> Self {
+        Factories { entries: Vec::new(), lookup: HashMap::new() }
+    }
+
+    // adds a factory, a factory with the same name is replaced in place
+    pub(crate) fn insert(&mut self, mut entry: FactoryEntry<'a, T>, symbols: &mut Symbols) {
+        entry.symbol = symbols.intern(&entry.name);
+
+        match self.lookup.get(&entry.symbol) {
+            Some(i) => self.entries[*i] = entry,
+            None => {
+                self.lookup.insert(entry.symbol, self.entries.len());
+                self.entries.push(entry);
+            },
+        }
+    }
+
+    // inserts and resolves in one go, leaving everything untouched on error
+    pub(crate) fn insert_resolved(&mut self, new_entries: Vec<FactoryEntry<'a, T>>, symbols: &mut Symbols) -> Result<(), FactoryError> {
+        let mut links: Vec<(&str, Option<&str>)> = self.entries.iter()
+            .map(|e| (e.name.as_str(), e.base.as_deref()))
+            .collect();
+
+        for entry in &new_entries {
+            match links.iter().position(|l| l.0 == entry.name) {
+                Some(i) => links[i] = (&entry.name, entry.base.as_deref()),
+                None => links.push((&entry.name, entry.base.as_deref())),
+            }
+        }
+        resolve_chains(&links)?;
+
+        for entry in new_entries {
+            self.insert(entry, symbols);
+        }
+        self.resolve()
+    }
+
+    pub(crate) fn resolve(&mut self) -> Result<(), FactoryError> {
+        let links: Vec<(&str, Option<&str>)> = self.entries.iter()
+            .map(|e| (e.name.as_str(), e.base.as_deref()))
+            .collect();
+
+        let chains = resolve_chains(&links)?;
+        for (entry, chain) in self.entries.iter_mut().zip(chains) {
+            entry.chain = chain;
+        }
+        Ok(())
+    }
+
+    // every component a factory declares has to be defined
+    pub(crate) fn check_components(&self, component_refs: &ComponentRefs) -> Result<(), FactoryError> {
+        for entry in &self.entries {
+            if let Some(name) = entry.factory.components().into_iter().find(|c| component_refs.get(c).is_none()) {
+                return Err(FactoryError::UnknownComponent { factory: entry.name.clone(), component: name.to_string() });
+            }
+        }
+        Ok(())
+    }
+
+    pub fn index_of(&self, type_name: &str) -> Option<usize> {
+        self.entries.iter().position(|e| e.name == type_name)
+    }
+
+    pub fn index_of_symbol(&self, type_name: Symbol) -> Option<usize> {
+        self.lookup.get(&type_name).copied()
+    }
+
+    pub fn symbol_of(&self, index: usize) -> Symbol {
+        self.entries[index].symbol
+    }
+
+    pub fn names(&self) -> Vec<&str> {
+        self.entries.iter().map(|e| e.name.as_str()).collect()
+    }
+
+    pub fn base_of(&self, type_name: &str) -> Option<&str> {
+        self.index_of(type_name).and_then(|i| self.entries[i].base.as_deref())
+    }
+
+    // runs the make_spawn of every base factory first, then the factory itself
+    pub(crate) fn make_spawn(&mut self, index: usize, tools: &mut BuildTools<T>) {
+        #[cfg(debug_assertions)]
+        let before = tools.entity.0;
+
+        for n in 0..self.entries[index].chain.len() {
+            let step = self.entries[index].chain[n];
+            self.entries[step].factory.make_spawn(tools);
+        }
+
+        #[cfg(debug_assertions)]
+        {
+            let declared = self.declared_components(index, tools.component_refs);
+            let added = (tools.entity.0 & !before) & !declared.0;
+            debug_assert!(added == 0, "factory '{}' added components it does not declare: {:?}",
+                self.entries[index].name,
+                tools.component_refs.decode(&BitFlags(added)).iter().map(|c| c.name().to_string()).collect::<Vec<_>>());
+        }
+    }
+
+    // component flags declared by a factory and its base factories
+    pub(crate) fn declared_components(&self, index: usize, component_refs: &ComponentRefs) -> BitFlags {
+        let mut flags = BitFlags::default();
+        for step in &self.entries[index].chain {
+            for name in self.entries[*step].factory.components() {
+                if let Some(component) = component_refs.get(&name) {
+                    flags.set_bit(*component.index(), true);
+                }
+            }
+        }
+        flags
+    }
+}
+
+fn resolve_chains(links: &[(&str, Option<&str>)]) -> Result<Vec<Vec<usize>>, FactoryError> {
+    let mut chains = Vec::with_capacity(links.len());
+
+    for (start, _) in links.iter().enumerate() {
+        let mut chain = vec![start];
+        let mut current = start;
+
+        while let Some(base) = links[current].1 {
+            let next = match links.iter().position(|l| l.0 == base) {
+                Some(next) => next,
+                None => return Err(FactoryError::UnknownBase {
+                    factory: links[current].0.to_string(),
+                    base: base.to_string(),
+                }),
+            };
+            if chain.contains(&next) {
+                let mut path: Vec<String> = chain.iter().map(|i| links[*i].0.to_string()).collect();
+                path.push(links[next].0.to_string());
+                return Err(FactoryError::Cycle(path));
+            }
+            chain.push(next);
+            current = next;
+        }
+        chain.reverse();
+        chains.push(chain);
+    }
+    Ok(chains)
+}
+
+
+// Spawn time arguments handed to a factory, looked up by key or by type:
+//
+//   SpawnArgs::new().with("team", 2u8).with_value(Position(1.0, 4.0))
+//
+#[derive(Default)]
+pub struct SpawnArgs {
+    values: Vec<(String, Box<dyn Any>)>,
+}
+
+impl SpawnArgs {
+    pub fn new() -> Self {
+        SpawnArgs { values: Vec::new() }
+    }
+
+    pub fn with<V: Any>(mut self, key: &str, value: V) -> Self {
+        self.set(key, value);
+        self
+    }
+
+    pub fn with_value<V: Any>(self, value: V) -> Self {
+        self.with("", value)
+    }
+
+    pub fn set<V: Any>(&mut self, key: &str, value: V) {
+        self.values.retain(|v| v.0 != key || !v.1.is::<V>());
+        self.values.push((key.to_string(), Box::new(value)));
+    }
+
+    pub fn get<V: Any>(&self, key: &str) -> Option<&V> {
+        self.values.iter()
+            .filter(|v| v.0 == key)
+            .find_map(|v| v.1.downcast_ref::<V>())
+    }
+
+    // first argument of type V, whatever its key
+    pub fn value<V: Any>(&self) -> Option<&V> {
+        self.values.iter().find_map(|v| v.1.downcast_ref::<V>())
+    }
+
+    pub fn contains(&self, key: &str) -> bool {
+        self.values.iter().any(|v| v.0 == key)
+    }
+
+    pub fn keys(&self) -> Vec<&str> {
+        self.values.iter().map(|v| v.0.as_str()).collect()
+    }
+
+    pub fn is_empty(&self) -> bool { self.values.is_empty() }
 }
 
 
@@ -11,6 +284,7 @@ pub struct BuildTools<'a, T> {
     object: &'a mut T,
     component_refs: &'a ComponentRefs,
     entity: &'a mut BitFlags,
+    args: Option<&'a SpawnArgs>,
 }
 
 impl<'a, T> BuildTools<'a, T> {
@@ -21,7 +295,29 @@ impl<'a, T> BuildTools<'a, T> {
         entity: &'a mut BitFlags,
 
     ) -> Self {
-        BuildTools { object, component_refs, entity }
+        BuildTools { object, component_refs, entity, args: None }
+    }
+
+    pub fn with_args(
+        object: &'a mut T,
+        component_refs: &'a ComponentRefs,
+        entity: &'a mut BitFlags,
+        args: &'a SpawnArgs,
+
+    ) -> Self {
+        BuildTools { object, component_refs, entity, args: Some(args) }
+    }
+
+    pub fn args(&self) -> Option<&SpawnArgs> {
+        self.args
+    }
+
+    pub fn arg<V: Any>(&self, key: &str) -> Option<&V> {
+        self.args.and_then(|a| a.get(key))
+    }
+
+    pub fn arg_value<V: Any>(&self) -> Option<&V> {
+        self.args.and_then(|a| a.value())
     }
 
     pub fn edit(&mut self) -> &mut T {
@@ -39,4 +335,28 @@ impl<'a, T> BuildTools<'a, T> {
             self.entity.set_bit(*component.index(), false);
         }
     }
+
+    // like the string versions, components never defined in the EcsBuilder are ignored,
+    // debug builds assert on them since finalize only checks what Factory::components declares
+    pub fn add<K: ComponentKey>(&mut self) {
+        if let Some(index) = self.typed_index::<K>() {
+            self.entity.set_bit(index, true);
+        }
+    }
+
+    pub fn remove<K: ComponentKey>(&mut self) {
+        if let Some(index) = self.typed_index::<K>() {
+            self.entity.set_bit(index, false);
+        }
+    }
+
+    pub fn has<K: ComponentKey>(&self) -> bool {
+        self.typed_index::<K>().is_some_and(|index| self.entity.bit(index))
+    }
+
+    fn typed_index<K: ComponentKey>(&self) -> Option<usize> {
+        let component = self.component_refs.of::<K>();
+        debug_assert!(component.is_some(), "component '{}' was not defined in the EcsBuilder", K::NAME);
+        component.map(|c| *c.index())
+    }
 }
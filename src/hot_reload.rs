@@ -0,0 +1,208 @@
+use std::fmt;
+use std::fs;
+use std::path::{ Path, PathBuf };
+use std::rc::Rc;
+use std::time::SystemTime;
+
+use libloading::Library;
+
+use super::*;
+use super::plugin::*;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PluginError {
+    Io(String),
+    Load(String),
+    AbiMismatch { expected: u32, found: u32 },
+    VersionMismatch { what: &'static str, expected: String, found: String },
+    ObjectMismatch { expected: ObjectLayout, found: ObjectLayout },
+    MissingBehaviour(String),
+}
+
+impl fmt::Display for PluginError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PluginError::Io(e) => write!(f, "plugin file error: {}", e),
+            PluginError::Load(e) => write!(f, "could not load plugin: {}", e),
+            PluginError::AbiMismatch { expected, found } =>
+                write!(f, "plugin abi version {} does not match {}", found, expected),
+            PluginError::VersionMismatch { what, expected, found } =>
+                write!(f, "plugin was built with {} '{}' instead of '{}'", what, found, expected),
+            PluginError::ObjectMismatch { expected, found } =>
+                write!(f, "plugin was built for object type {:?} instead of {:?}", found, expected),
+            PluginError::MissingBehaviour(name) =>
+                write!(f, "reloaded plugin no longer exports behaviour '{}'", name),
+        }
+    }
+}
+
+impl std::error::Error for PluginError {}
+
+
+// Keeps the library loaded for as long as a behaviour from it is alive.
+// Field order matters: the behaviour has to be dropped before the library.
+struct PluginBehaviour<T: Default> {
+    inner: Box<dyn Behaviour<T>>,
+    _library: Rc<Library>,
+}
+
+impl<T: Default> Behaviour<T> for PluginBehaviour<T> {
+    fn required_components(&self) -> Vec<NameTag> { self.inner.required_components() }
+    fn access(&self) -> Access { self.inner.access() }
+    fn updates_entities(&self) -> bool { self.inner.updates_entities() }
+
+    fn on_startup(&mut self, objects: &mut Objects<T>, system: &mut System) {
+        self.inner.on_startup(objects, system)
+    }
+
+    fn on_early_update(&mut self, objects: &mut Objects<T>, system: &mut System) {
+        self.inner.on_early_update(objects, system)
+    }
+
+    fn on_update(&mut self, target: &ObjectIndex, objects: &mut Objects<T>, system: &mut System) {
+        self.inner.on_update(target, objects, system)
+    }
+}
+
+
+// Behaviours loaded from a cdylib on local disk that can be swapped while the Ecs keeps
+// running. Objects and entities are left alone on reload, only the behaviours change.
+//
+//   let mut plugin = HotPlugin::<Cell>::load("target/debug/libgameplay.so")?;
+//   let mut ecs = EcsBuilder::new(100)
+//       .build_systems()
+//           .define_hot_systems(&mut plugin)
+//       ...
+//   loop {
+//       plugin.reload_if_changed(&mut ecs)?;
+//       ecs.update();
+//   }
+//
+pub struct HotPlugin<T: Default> {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+    loads: usize,
+    loaded: Vec<(String, PluginBehaviour<T>)>, // behaviours of the last load not handed out yet
+    systems: Vec<(String, SystemIndex)>,
+}
+
+impl<T: Default + 'static> HotPlugin<T> {
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, PluginError> {
+        let path = path.as_ref().to_path_buf();
+        let modified = modified_time(&path);
+        let loaded = open_plugin::<T>(&path, 0)?;
+
+        Ok(HotPlugin { path, modified, loads: 1, loaded, systems: Vec::new() })
+    }
+
+    pub fn path(&self) -> &Path { &self.path }
+
+    pub fn behaviour_names(&self) -> Vec<&str> {
+        self.systems.iter().map(|s| s.0.as_str())
+            .chain(self.loaded.iter().map(|b| b.0.as_str()))
+            .collect()
+    }
+
+    // loads the library again and swaps every system defined by define_hot_systems,
+    // nothing is swapped when the new library can't provide all of them
+    pub fn reload<'a>(&mut self, ecs: &mut Ecs<'a, T>) -> Result<(), PluginError>
+    where T: Debug {
+        let mut loaded = open_plugin::<T>(&self.path, self.loads)?;
+        self.loads += 1;
+        self.modified = modified_time(&self.path);
+
+        for (name, _) in &self.systems {
+            if !loaded.iter().any(|b| b.0 == *name) {
+                return Err(PluginError::MissingBehaviour(name.clone()));
+            }
+        }
+        for (name, system) in &self.systems {
+            if let Some(i) = loaded.iter().position(|b| b.0 == *name) {
+                let (_, behaviour) = loaded.swap_remove(i);
+                ecs.replace_behaviour(*system, Box::new(behaviour));
+            }
+        }
+        // the previous library unloads once the replaced behaviours are gone
+        self.loaded = loaded;
+        Ok(())
+    }
+
+    pub fn reload_if_changed<'a>(&mut self, ecs: &mut Ecs<'a, T>) -> Result<bool, PluginError>
+    where T: Debug {
+        let modified = modified_time(&self.path);
+        if modified.is_none() || modified == self.modified {
+            return Ok(false);
+        }
+        self.reload(ecs)?;
+        Ok(true)
+    }
+}
+
+impl<T: Default + 'static> SystemBuilder<T> {
+    // defines a system for every behaviour the plugin exports
+    pub fn define_hot_systems(mut self, plugin: &mut HotPlugin<T>) -> Self {
+        for (name, behaviour) in plugin.loaded.drain(..) {
+            plugin.systems.push((name, self.systems.len()));
+            self = self.define_system(Box::new(behaviour));
+        }
+        self
+    }
+}
+
+fn modified_time(path: &Path) -> Option<SystemTime> {
+    fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+// The library is loaded from a copy, so the original can be rebuilt while the copy is
+// in use and the loader doesn't hand back the previous load for the same path.
+fn open_plugin<T: Default + 'static>(path: &Path, load: usize) -> Result<Vec<(String, PluginBehaviour<T>)>, PluginError> {
+    let file_name = path.file_name()
+        .map(|f| f.to_string_lossy().to_string())
+        .ok_or_else(|| PluginError::Io(format!("'{}' is not a file", path.display())))?;
+    let copy = std::env::temp_dir().join(format!("simple_ecs_{}_{}_{}", std::process::id(), load, file_name));
+    fs::copy(path, &copy).map_err(|e| PluginError::Io(format!("{}: {}", path.display(), e)))?;
+
+    let library = unsafe { Library::new(&copy) };
+    let _ = fs::remove_file(&copy);
+    let library = library.map_err(|e| PluginError::Load(e.to_string()))?;
+
+    let declaration: &PluginDeclaration = unsafe {
+        match library.get::<*const PluginDeclaration>(b"SIMPLE_ECS_PLUGIN\0") {
+            Ok(symbol) => &**symbol,
+            Err(e) => return Err(PluginError::Load(e.to_string())),
+        }
+    };
+
+    if declaration.abi_version != PLUGIN_ABI_VERSION {
+        return Err(PluginError::AbiMismatch { expected: PLUGIN_ABI_VERSION, found: declaration.abi_version });
+    }
+    if declaration.ecs_version != ECS_VERSION {
+        return Err(PluginError::VersionMismatch {
+            what: "simple_ecs",
+            expected: ECS_VERSION.to_string(),
+            found: declaration.ecs_version.to_string(),
+        });
+    }
+    if declaration.rustc_version != RUSTC_VERSION {
+        return Err(PluginError::VersionMismatch {
+            what: "rustc",
+            expected: RUSTC_VERSION.to_string(),
+            found: declaration.rustc_version.to_string(),
+        });
+    }
+    let expected = ObjectLayout::of::<T>();
+    let found = (declaration.object_layout)();
+    if found != expected {
+        return Err(PluginError::ObjectMismatch { expected, found });
+    }
+
+    let mut registrar = PluginRegistrar::<T>::default();
+    unsafe { (declaration.register)(&mut registrar as *mut PluginRegistrar<T> as *mut ()) };
+
+    let library = Rc::new(library);
+    Ok(registrar.behaviours.into_iter()
+        .map(|(name, inner)| (name, PluginBehaviour { inner, _library: library.clone() }))
+        .collect())
+}
@@ -0,0 +1,346 @@
+use std::fmt::Debug;
+use std::fmt::Write as _;
+use std::io::{ self, Read, Write, ErrorKind };
+use std::net::{ TcpListener, TcpStream, SocketAddr, ToSocketAddrs };
+#[cfg(unix)]
+use std::os::unix::net::{ UnixListener, UnixStream };
+#[cfg(unix)]
+use std::path::{ Path, PathBuf };
+
+use super::{ Ecs, ObjectIndex, DumpFilter };
+
+// longest command line a client may send before it is disconnected
+const MAX_LINE: usize = 4096;
+
+// most output a client may leave unread before it is disconnected
+const MAX_OUTPUT: usize = 4 << 20;
+
+const HELP: &str = "\
+list [named <name> | with <component>]
+show <object>
+get <object> <field>
+set <object> <field> <value>
+fields
+systems
+enable <system>
+disable <system>
+factories
+spawn <factory> [name]
+destroy <object>
+dump
+objects are given by index or by name";
+
+type Getter<T> = Box<dyn Fn(&T) -> String>;
+type Setter<T> = Box<dyn Fn(&mut T, &str) -> Result<(), String>>;
+
+enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener, PathBuf),
+}
+
+enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.read(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.read(buf),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(s) => s.write(buf),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(s) => s.flush(),
+            #[cfg(unix)]
+            Stream::Unix(s) => s.flush(),
+        }
+    }
+}
+
+struct Client {
+    stream: Stream,
+    input: Vec<u8>,
+    output: Vec<u8>, // what the socket didn't take yet
+    closed: bool,
+}
+
+
+// Serves a line based protocol to a terminal tool, `nc localhost 7777` will do:
+//
+//   let mut inspector = Inspector::bind_tcp("127.0.0.1:7777")?
+//       .field("health", |cell: &Cell| cell.health.to_string(), |cell, value| {
+//           cell.health = value.parse().map_err(|e| format!("{}", e))?;
+//           Ok(())
+//       });
+//
+//   loop {
+//       ecs.update();
+//       inspector.poll(&mut ecs);
+//   }
+//
+// Every reply is zero or more lines followed by `ok` or `error <message>`. Sockets are
+// non-blocking, so polling never waits for a client.
+pub struct Inspector<T> {
+    listener: Listener,
+    clients: Vec<Client>,
+    fields: Vec<(String, Getter<T>, Setter<T>)>,
+}
+
+impl<T: Default + Debug> Inspector<T> {
+    // only loopback addresses are accepted
+    pub fn bind_tcp<A: ToSocketAddrs>(addr: A) -> io::Result<Self> {
+        let addrs: Vec<SocketAddr> = addr.to_socket_addrs()?.collect();
+        if addrs.is_empty() || addrs.iter().any(|a| !a.ip().is_loopback()) {
+            return Err(io::Error::new(ErrorKind::InvalidInput, "the inspector only listens on localhost"));
+        }
+        let listener = TcpListener::bind(&addrs[..])?;
+        listener.set_nonblocking(true)?;
+        Ok(Self::new(Listener::Tcp(listener)))
+    }
+
+    // the socket file is removed again when the inspector is dropped
+    #[cfg(unix)]
+    pub fn bind_unix<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let listener = UnixListener::bind(path.as_ref())?;
+        listener.set_nonblocking(true)?;
+        Ok(Self::new(Listener::Unix(listener, path.as_ref().to_path_buf())))
+    }
+
+    fn new(listener: Listener) -> Self {
+        Inspector { listener, clients: Vec::new(), fields: Vec::new() }
+    }
+
+    // makes a field of T readable and writable with get and set
+    pub fn field<G, S>(mut self, name: &str, get: G, set: S) -> Self
+    where G: Fn(&T) -> String + 'static, S: Fn(&mut T, &str) -> Result<(), String> + 'static {
+        self.fields.push((name.to_string(), Box::new(get), Box::new(set)));
+        self
+    }
+
+    // None for unix sockets
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        match &self.listener {
+            Listener::Tcp(l) => l.local_addr().ok(),
+            #[cfg(unix)]
+            Listener::Unix(..) => None,
+        }
+    }
+
+    pub fn clients(&self) -> usize {
+        self.clients.len()
+    }
+
+    // Accepts new clients and runs the commands that arrived since the last poll, call it
+    // between updates. Returns the number of commands run.
+    pub fn poll(&mut self, ecs: &mut Ecs<T>) -> usize {
+        self.accept();
+
+        let mut handled = 0;
+        let mut clients = std::mem::take(&mut self.clients);
+        for client in &mut clients {
+            receive(client);
+            while let Some(end) = client.input.iter().position(|b| *b == b'\n') {
+                let line: Vec<u8> = client.input.drain(..=end).collect();
+                let line = String::from_utf8_lossy(&line);
+                let line = line.trim();
+                if line.is_empty() {
+                    continue;
+                }
+                let mut reply = String::new();
+                match self.execute(line, ecs, &mut reply) {
+                    Ok(()) => reply.push_str("ok\n"),
+                    Err(message) => { let _ = writeln!(reply, "error {}", message); },
+                }
+                client.output.extend_from_slice(reply.as_bytes());
+                handled += 1;
+            }
+            if client.input.len() > MAX_LINE {
+                client.closed = true;
+            }
+            send(client);
+            if client.output.len() > MAX_OUTPUT {
+                client.closed = true;
+            }
+        }
+        clients.retain(|c| !c.closed);
+        self.clients = clients;
+        handled
+    }
+
+    fn accept(&mut self) {
+        loop {
+            let stream = match &self.listener {
+                Listener::Tcp(l) => l.accept().and_then(|(s, _)| s.set_nonblocking(true).map(|_| Stream::Tcp(s))),
+                #[cfg(unix)]
+                Listener::Unix(l, _) => l.accept().and_then(|(s, _)| s.set_nonblocking(true).map(|_| Stream::Unix(s))),
+            };
+            match stream {
+                Ok(stream) => self.clients.push(Client { stream, input: Vec::new(), output: Vec::new(), closed: false }),
+                Err(_) => return,
+            }
+        }
+    }
+
+    fn execute(&self, line: &str, ecs: &mut Ecs<T>, out: &mut String) -> Result<(), String> {
+        let mut words = line.split_whitespace();
+        let command = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        let arg = |i: usize, what: &str| args.get(i).copied().ok_or_else(|| format!("missing {}", what));
+
+        match command {
+            "help" => { let _ = writeln!(out, "{}", HELP); },
+            "list" => {
+                let filter = match args.as_slice() {
+                    [] => DumpFilter::All,
+                    ["named", name] => DumpFilter::Name(name.to_string()),
+                    ["with", component] => DumpFilter::Component(component.to_string()),
+                    _ => return Err("expected list [named <name> | with <component>]".to_string()),
+                };
+                for e in ecs.dump_matching(filter).entities {
+                    let _ = writeln!(out, "{} '{}' [{}]", e.index, e.name, e.components.join(", "));
+                }
+            },
+            "show" => {
+                let target = object(ecs, arg(0, "object")?)?;
+                let _ = writeln!(out, "{:#?}", ecs.get_ref(&target));
+                for (name, get, _) in &self.fields {
+                    let _ = writeln!(out, "{} = {}", name, get(ecs.get_ref(&target)));
+                }
+            },
+            "get" => {
+                let target = object(ecs, arg(0, "object")?)?;
+                let (_, get, _) = self.accessor(arg(1, "field")?)?;
+                let _ = writeln!(out, "{}", get(ecs.get_ref(&target)));
+            },
+            "set" => {
+                let target = object(ecs, arg(0, "object")?)?;
+                let (_, _, set) = self.accessor(arg(1, "field")?)?;
+                // the value is the rest of the line, so it may hold spaces
+                let value = after_words(line, 3);
+                if value.is_empty() {
+                    return Err("missing value".to_string());
+                }
+                set(ecs.get_mut(&target), value)?;
+            },
+            "fields" => {
+                for (name, ..) in &self.fields {
+                    let _ = writeln!(out, "{}", name);
+                }
+            },
+            "systems" => {
+                for s in &ecs.systems {
+                    let _ = writeln!(out, "{} {} {}", s.index, if s.enabled { "on" } else { "off" }, s.label(&ecs.component_refs));
+                }
+            },
+            "enable" | "disable" => {
+                let system = arg(0, "system")?.parse().map_err(|_| "expected a system index")?;
+                if !ecs.set_system_enabled(system, command == "enable") {
+                    return Err(format!("no system {}", system));
+                }
+            },
+            "factories" => {
+                // the components each factory declares, listing never runs a factory
+                for (name, components) in ecs.resolved_factories() {
+                    let _ = writeln!(out, "{} [{}]", name, components.join(", "));
+                }
+            },
+            "spawn" => {
+                let factory = arg(0, "factory")?;
+                if ecs.factories().index_of(factory).is_none() {
+                    return Err(format!("unknown factory '{}'", factory));
+                }
+                match ecs.spawn(args.get(1).copied().unwrap_or(""), factory) {
+                    Some(target) => { let _ = writeln!(out, "{}", target); },
+                    None => return Err("the world is full or the name is taken".to_string()),
+                }
+            },
+            "destroy" => {
+                let target = object(ecs, arg(0, "object")?)?;
+                ecs.destroy(&target);
+            },
+            "dump" => { let _ = writeln!(out, "{}", ecs.dump().to_json()); },
+            _ => return Err(format!("unknown command '{}', try help", command)),
+        }
+        Ok(())
+    }
+
+    fn accessor(&self, name: &str) -> Result<&(String, Getter<T>, Setter<T>), String> {
+        self.fields.iter().find(|f| f.0 == name).ok_or_else(|| format!("unknown field '{}'", name))
+    }
+}
+
+#[cfg(unix)]
+impl<T> Drop for Inspector<T> {
+    fn drop(&mut self) {
+        if let Listener::Unix(_, path) = &self.listener {
+            let _ = std::fs::remove_file(path);
+        }
+    }
+}
+
+// an alive object by index, or else by name
+fn object<T: Default + Debug>(ecs: &Ecs<T>, word: &str) -> Result<ObjectIndex, String> {
+    match word.parse::<ObjectIndex>() {
+        Ok(index) if ecs.is_alive(&index) => Ok(index),
+        Ok(index) => Err(format!("object {} is not alive", index)),
+        Err(_) => ecs.find(word).ok_or_else(|| format!("no object named '{}'", word)),
+    }
+}
+
+// what follows the first `count` whitespace separated words, trimmed
+fn after_words(line: &str, count: usize) -> &str {
+    let mut rest = line.trim_start();
+    for _ in 0..count {
+        let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        rest = rest[end..].trim_start();
+    }
+    rest.trim_end()
+}
+
+fn receive(client: &mut Client) {
+    let mut buf = [0u8; 1024];
+    loop {
+        match client.stream.read(&mut buf) {
+            Ok(0) => { client.closed = true; return; },
+            Ok(n) => client.input.extend_from_slice(&buf[..n]),
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => {
+                client.closed |= e.kind() != ErrorKind::WouldBlock;
+                return;
+            },
+        }
+        if client.input.len() > MAX_LINE {
+            return;
+        }
+    }
+}
+
+fn send(client: &mut Client) {
+    while !client.output.is_empty() {
+        match client.stream.write(&client.output) {
+            Ok(0) => { client.closed = true; return; },
+            Ok(n) => { client.output.drain(..n); },
+            Err(e) if e.kind() == ErrorKind::Interrupted => continue,
+            Err(e) => {
+                client.closed |= e.kind() != ErrorKind::WouldBlock;
+                return;
+            },
+        }
+    }
+}
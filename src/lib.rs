@@ -1,15 +1,58 @@
 
+// lets the derive macros refer to ::simple_ecs from within this crate as well
+extern crate self as simple_ecs;
+
 mod pool;
 mod systems;
 mod factory;
 mod types;
+mod prefab;
+mod plugin;
+mod reflect;
+mod replication;
+mod profiler;
+mod dump;
+mod json;
+mod record;
+mod transfer;
+mod scene;
+mod spatial;
+#[cfg(feature = "scripting")]
+mod script;
+#[cfg(feature = "hot-reload")]
+mod hot_reload;
+#[cfg(feature = "inspector")]
+mod inspector;
+#[cfg(test)]
 mod tests;
 
 use std::fmt::Debug;
-pub use pool::{ Objects, Entities };
-pub use systems::{ System, Behaviour };
-pub use types::{ NameTag, ComponentRefs, ComponentRef, BitFlags };
+use std::fmt::Write as _;
+use std::path::Path;
+use std::time::Instant;
+pub use pool::{ Objects, Entities, GrowthPolicy, CapacityReport, NamePolicy };
+pub use systems::{ System, Behaviour, Access, FnBehaviour };
+pub use types::{ NameTag, Symbol, Symbols, ComponentKey, ComponentRefs, ComponentRef, BitFlags };
 pub use factory::*;
+pub use simple_ecs_derive::{ Component, Reflect, system };
+pub use plugin::{ PluginDeclaration, PluginRegistrar, ObjectLayout, PLUGIN_ABI_VERSION, ECS_VERSION, RUSTC_VERSION };
+#[cfg(feature = "hot-reload")]
+pub use hot_reload::{ HotPlugin, PluginError };
+pub use reflect::{ Reflect, Value, ReflectError, FieldInfo };
+pub use profiler::{ ProfileReport, SystemProfile, Timing };
+use profiler::Profiler;
+use spatial::SpatialIndex;
+pub use dump::{ WorldDump, EntityDump, SystemDump, DumpFilter };
+pub use record::{ Command, CommandError, Recording, Recorder, ReplayError };
+use record::Checksum;
+pub use transfer::{ EntityMap, TransferError };
+pub use scene::{ SceneId, SceneError, SceneEntry, parse_scene };
+pub use replication::{ Replication, ReplicationServer, ReplicationClient, ReplicationError, Transport, Loopback, ClientId };
+#[cfg(feature = "inspector")]
+pub use inspector::Inspector;
+#[cfg(feature = "scripting")]
+pub use script::{ ScriptBehaviour, ScriptError };
+pub use prefab::{ PrefabData, PrefabError, Prefab, PrefabFactory, parse_prefabs };
 
 pub type ObjectIndex = usize;
 pub type ComponentIndex = usize;
@@ -19,26 +62,76 @@ pub type SystemIndex = usize;
 // start by defining components
 pub struct EcsBuilder {
     size: usize,
+    growth: GrowthPolicy,
+    names: Option<NamePolicy>,
+    profile: Option<usize>,
+    symbols: Symbols,
     component_refs: ComponentRefs,
 }
 
 impl EcsBuilder {
 
     pub fn new(size: usize) -> Self {
-        EcsBuilder{ size, component_refs: ComponentRefs(Vec::new()) }
+        EcsBuilder{ 
+            size, 
+            growth: GrowthPolicy::Fixed, 
+            names: None, 
+            profile: None,
+            symbols: Symbols::new(),
+            component_refs: ComponentRefs::new(),
+        }
+    }
+
+    // by default the world holds at most `size` objects
+    pub fn with_growth(mut self, growth: GrowthPolicy) -> Self {
+        self.growth = growth;
+        self
+    }
+
+    // keeps a hash index of object names, making find independent of the amount of objects
+    pub fn with_name_index(mut self, policy: NamePolicy) -> Self {
+        self.names = Some(policy);
+        self
+    }
+
+    // records per system timings of the last `window` frames, see Ecs::profile_report
+    pub fn with_profiler(mut self, window: usize) -> Self {
+        self.profile = Some(window);
+        self
     }
 
     pub fn define_component(mut self, name: &str) -> Self {
-        self.component_refs.0.push(ComponentRef::new(self.component_refs.0.len(), name));
+        let symbol = self.symbols.intern(name);
+        self.component_refs.push(name, symbol, false);
         self
     }
 
+    // A component without data, only its bit in the signature. Systems and queries
+    // treat it like any other component, but it has no added tick of its own.
+    pub fn define_tag(mut self, name: &str) -> Self {
+        let symbol = self.symbols.intern(name);
+        self.component_refs.push(name, symbol, true);
+        self
+    }
+
+    // defines the component of a typed key, see ComponentKey and components!
+    pub fn define<K: ComponentKey>(self) -> Self {
+        self.define_component(K::NAME)
+    }
+
     pub fn build_systems<T: Default>(self) -> SystemBuilder<T> {
         SystemBuilder {
             size: self.size,
+            growth: self.growth,
+            names: self.names,
+            profile: self.profile,
+            symbols: self.symbols,
             component_refs: self.component_refs,
             systems: Vec::new(),
             behaviours: Vec::new(),
+            unknown: Vec::new(),
+            spatial: None,
+            unknown_spatial: None,
         }
     }
 }
@@ -46,33 +139,75 @@ impl EcsBuilder {
 // secondly define systems
 pub struct SystemBuilder<T: Default> {
     size: usize,
+    growth: GrowthPolicy,
+    names: Option<NamePolicy>,
+    profile: Option<usize>,
+    symbols: Symbols,
     component_refs: ComponentRefs, 
     systems: Vec<System>, 
-    behaviours: Vec<Box<dyn Behaviour<T>>>
+    behaviours: Vec<Box<dyn Behaviour<T>>>,
+    unknown: Vec<(SystemIndex, NameTag)>, // undefined components systems refer to, reported by finalize
+    spatial: Option<SpatialIndex<T>>,
+    unknown_spatial: Option<String>,
 }
 
 impl<'a, T: Default> SystemBuilder<T> {
 
     pub fn define_system(mut self, behaviour: Box<dyn Behaviour<T>>) -> Self {
-        let mut components = BitFlags (0);
-
-        for s in &mut behaviour.required_components().iter() {
-            if let Some(c) = self.component_refs.get(s) {
-                components.set_bit(*c.index(), true)
-            }
+        let mut system = System::new(self.systems.len());
+        for name in system.describe(behaviour.as_ref(), &self.component_refs) {
+            self.unknown.push((system.index, name));
         }
+
         self.behaviours.push(behaviour);
-        self.systems.push(System::new(self.systems.len(), components));
+        self.systems.push(system);
+        self
+    }
+
+    // one-liner systems from closures, see FnBehaviour for combining several hooks
+    pub fn define_system_fn<F>(self, components: &[&str], on_update: F) -> Self
+    where T: 'static, F: FnMut(&ObjectIndex, &mut Objects<T>, &mut System) + 'static {
+        self.define_system(Box::new(FnBehaviour::new(components).on_update(on_update)))
+    }
+
+    pub fn define_early_update_fn<F>(self, on_early_update: F) -> Self
+    where T: 'static, F: FnMut(&mut Objects<T>, &mut System) + 'static {
+        self.define_system(Box::new(FnBehaviour::new(&[]).on_early_update(on_early_update)))
+    }
+
+    pub fn define_startup_fn<F>(self, on_startup: F) -> Self
+    where T: 'static, F: FnMut(&mut Objects<T>, &mut System) + 'static {
+        self.define_system(Box::new(FnBehaviour::new(&[]).on_startup(on_startup)))
+    }
+
+    // Keeps the objects holding the component in a grid of cell_size, for
+    // Objects::query_radius and friends. An undefined component is reported by finalize.
+    pub fn with_spatial_index<F>(mut self, component: &str, cell_size: f32, position: F) -> Self
+    where F: Fn(&T) -> (f32, f32) + 'static {
+        match self.component_refs.get(&NameTag::from_str(component)) {
+            Some(c) => {
+                self.spatial = Some(SpatialIndex::new(*c.index(), cell_size, Box::new(position), self.size));
+                self.unknown_spatial = None;
+            },
+            None => self.unknown_spatial = Some(component.to_string()),
+        }
         self
     }
 
     pub fn setup_factories(self) -> FactoryBuilder<'a, T> {
         FactoryBuilder { 
             size: self.size,
+            growth: self.growth,
+            names: self.names,
+            profile: self.profile,
+            symbols: self.symbols,
             systems: self.systems,
             behaviours: self.behaviours,
             component_refs: self.component_refs,
-            factories: Vec::new()
+            unknown: self.unknown,
+            spatial: self.spatial,
+            unknown_spatial: self.unknown_spatial,
+            factories: Factories::new(),
         }
     }
 }
@@ -80,86 +215,216 @@ impl<'a, T: Default> SystemBuilder<T> {
 // secondly define systems
 pub struct FactoryBuilder<'a, T: Default> {
     size: usize,
+    growth: GrowthPolicy,
+    names: Option<NamePolicy>,
+    profile: Option<usize>,
+    symbols: Symbols,
     component_refs: ComponentRefs, 
     systems: Vec<System>, 
     behaviours: Vec<Box<dyn Behaviour<T>>>,
-    factories: Vec<(String, Box<dyn Factory<'a, T>>)>,
+    unknown: Vec<(SystemIndex, NameTag)>,
+    spatial: Option<SpatialIndex<T>>,
+    unknown_spatial: Option<String>,
+    factories: Factories<'a, T>,
 }
 
 
 impl<'a, T: Default + Debug> FactoryBuilder<'a, T> {
 
     pub fn define_factory(mut self, type_name: &str, spawn_factory: Box<dyn Factory<'a, T>>) -> Self {
-        self.factories.push((type_name.to_string(), spawn_factory));
+        self.factories.insert(FactoryEntry::new(type_name, None, spawn_factory), &mut self.symbols);
         self
     }
 
+    // the base factory runs first, after which spawn_factory adds to or overrides its work
+    pub fn extend_factory(mut self, type_name: &str, base_name: &str, spawn_factory: Box<dyn Factory<'a, T>>) -> Self {
+        self.factories.insert(FactoryEntry::new(type_name, Some(base_name), spawn_factory), &mut self.symbols);
+        self
+    }
+
+    // registers one factory per [prefab] section found in the file
+    pub fn load_prefabs<P: AsRef<Path>>(self, path: P) -> Result<Self, PrefabError> 
+    where T: PrefabData + 'static {
+        let (file, source) = prefab::read_prefabs(path)?;
+        self.define_prefabs(&file, &source)
+    }
+
+    pub fn define_prefabs(mut self, file: &str, source: &str) -> Result<Self, PrefabError> 
+    where T: PrefabData + 'static {
+        let prefabs = prefab::parse_prefabs(file, source)?;
+        for factory in prefab::compile_prefabs(file, &prefabs, &self.component_refs)? {
+            self.factories.insert(factory, &mut self.symbols);
+        }
+        Ok(self)
+    }
+
+    // panics when factories extend unknown factories or extend each other in a cycle, and
+    // when factories, systems or the spatial index refer to components that were not defined
     pub fn finalize(self) -> Ecs<'a, T> {
-        Ecs { 
-            size: self.size,
-            objects: Objects::new(self.size),
-            entities: Entities::new(self.size),
+        match self.try_finalize() {
+            Ok(ecs) => ecs,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    pub fn try_finalize(mut self) -> Result<Ecs<'a, T>, FactoryError> {
+        if let Some((system, name)) = self.unknown.first() {
+            return Err(FactoryError::UnknownSystemComponent { system: *system, component: name.to_string() });
+        }
+        if let Some(component) = self.unknown_spatial.take() {
+            return Err(FactoryError::UnknownSpatialComponent(component));
+        }
+        self.factories.resolve()?;
+        self.factories.check_components(&self.component_refs)?;
+
+        let component_refs = &self.component_refs;
+        let profiler = self.profile.map(|window| {
+            let labels = self.systems.iter().map(|system| system.label(component_refs)).collect();
+            Profiler::new(window, labels)
+        });
+
+        let mut objects = Objects::new(self.size, self.component_refs.list().len(), self.names, self.symbols);
+        objects.spatial = self.spatial;
+
+        Ok(Ecs { 
+            objects,
+            entities: Entities::new(self.size, self.growth, &self.component_refs),
             systems: self.systems,
             behaviours: self.behaviours,
             component_refs: self.component_refs,
             factories: self.factories,
-        }
+            profiler,
+        })
     }
 }
 
 
 // actual core ECS system
 pub struct Ecs<'a, T: Default> { 
-    size: usize,
     objects: Objects<T>, // object data pool, in other words entity component data
     entities: Entities, // object component implementation flags
     systems: Vec<System>, // behaviour wrappers for executing custom behaviour scripts
     behaviours: Vec<Box<dyn Behaviour<T>>>,
     component_refs: ComponentRefs, // component definitions, flag position & amount of components available
-    factories: Vec<(String, Box<dyn Factory<'a, T>>)>, // used for spawning predefined objects
+    factories: Factories<'a, T>, // used for spawning predefined objects
+    profiler: Option<Profiler>,
 }
 
 impl<'a, T: Default + Debug> Ecs<'a, T> {
 
     pub fn start(&mut self) {
+        self.objects.tick += 1;
         // update routine
         for system in &mut self.systems {
+            self.objects.writing = BitFlags(system.writes.0);
             self.behaviours[system.index].on_startup(&mut self.objects, system);
         }
         // handle requests
         for system in &mut self.systems {
+            self.objects.writing = BitFlags(system.writes.0);
             self.behaviours[system.index].on_early_update(&mut self.objects, system);
+            self.objects.writing = BitFlags::all(self.objects.components);
 
-            if system.destroy_requests.len() > 0 || system.spawn_requests.len() > 0 {
+            if system.has_requests() {
                 system.handle_requests(&mut self.objects, &mut self.entities, &mut self.factories, &self.component_refs);
             }
         }
     }
 
     pub fn update(&mut self) {
-        // update routine
+        if let Some(profiler) = &mut self.profiler { profiler.begin_frame() }
+
+        // update routine, every system runs at its own tick so it sees what the
+        // systems before it changed, and what the systems after it changed next time
         for system in &mut self.systems {
+            if !system.enabled {
+                continue;
+            }
+            self.objects.tick += 1;
+            self.objects.writing = BitFlags(system.writes.0);
+            system.collect_removed(&self.entities);
+            let started = self.profiler.as_ref().map(|_| Instant::now());
             self.behaviours[system.index].on_early_update(&mut self.objects, system);
 
-            for pointer in &self.entities.active {
-                if system.components.0 == 
-                    self.entities.pool[*pointer].0 & system.components.0 
-                {
-                    self.behaviours[system.index].on_update(&pointer, &mut self.objects, system);
+            let updated = started.map(|_| Instant::now());
+            let mut matched = 0;
+            if system.updates_entities {
+                let filtered = system.has_filters();
+                for pointer in &self.entities.active {
+                    if system.matches(&self.entities.pool[*pointer]) 
+                        && (!filtered || system.passes_filters(*pointer, &self.entities, &self.objects)) {
+                        self.behaviours[system.index].on_update(pointer, &mut self.objects, system);
+                        matched += 1;
+                    }
                 }
             }
+            system.last_run = self.objects.tick;
+            self.objects.writing = BitFlags::all(self.objects.components);
+
+            if let (Some(profiler), Some(started), Some(updated)) = (&mut self.profiler, started, updated) {
+                profiler.record_system(system.index, started, updated, matched);
+            }
         }
         // handle requests
+        self.objects.tick += 1;
         for system in &mut self.systems {
-            if system.destroy_requests.len() > 0 || system.spawn_requests.len() > 0 {
+            if system.has_requests() {
+                let started = self.profiler.as_ref().map(|_| Instant::now());
                 system.handle_requests(&mut self.objects, &mut self.entities, &mut self.factories, &self.component_refs);
+
+                if let (Some(profiler), Some(started)) = (&mut self.profiler, started) {
+                    profiler.record_requests(system.index, started);
+                }
             }
         }
+        let seen = self.systems.iter()
+            .filter(|s| s.enabled && !s.watched.is_empty())
+            .map(|s| s.removed_since())
+            .min().unwrap_or(self.objects.tick);
+        self.entities.trim_removed(seen);
+
+        if let Some(profiler) = &mut self.profiler { profiler.end_frame() }
+    }
+
+    // updates with the frame time systems read through Objects::dt, update on its own
+    // keeps the frame time of the previous frame
+    pub fn update_dt(&mut self, dt: f32) {
+        self.objects.dt = dt;
+        self.update();
     }
 
+    // None unless the profiler was enabled with EcsBuilder::with_profiler
+    pub fn profile_report(&self) -> Option<ProfileReport> {
+        self.profiler.as_ref().map(|p| p.report())
+    }
+
+    // the frames in the profiler window in Chrome trace event format
+    pub fn chrome_trace(&self) -> Option<String> {
+        self.profiler.as_ref().map(|p| p.chrome_trace())
+    }
+
+    pub fn write_chrome_trace<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        match &self.profiler {
+            Some(profiler) => profiler.write_chrome_trace(path),
+            None => Err(std::io::Error::other("the profiler is not enabled")),
+        }
+    }
+
+    // every alive object with its components, the systems matching it and the free list,
+    // prints as text and converts to JSON with WorldDump::to_json
+    pub fn dump(&self) -> WorldDump {
+        self.dump_matching(DumpFilter::All)
+    }
+
+    pub fn dump_matching(&self, filter: DumpFilter) -> WorldDump {
+        dump::dump_world(filter, &self.objects, &self.entities, &self.systems, &self.component_refs)
+    }
+
+    // every object handed to the closure counts as changed
     pub fn open_update<F>(&mut self, mut update_methode: F )
     where F: FnMut(&usize, &mut Vec<T>) {
         for pointer in &self.entities.active {
+            self.objects.mark_changed(*pointer);
             update_methode(pointer, &mut self.objects.pool);
         }
     }
@@ -168,18 +433,123 @@ impl<'a, T: Default + Debug> Ecs<'a, T> {
         &self.component_refs
     }
 
-    // pub fn add_component(&mut self, target: &usize, component: &ComponentIndex) {
-    //     self.entities.pool[*target].set_bit(*component, true);
-    // }
+    // prefabs can also be (re)loaded while running, later definitions win
+    pub fn load_prefabs<P: AsRef<Path>>(&mut self, path: P) -> Result<(), PrefabError> 
+    where T: PrefabData + 'static {
+        let (file, source) = prefab::read_prefabs(path)?;
+        self.define_prefabs(&file, &source)
+    }
+
+    pub fn define_prefabs(&mut self, file: &str, source: &str) -> Result<(), PrefabError> 
+    where T: PrefabData + 'static {
+        let prefabs = prefab::parse_prefabs(file, source)?;
+        let factories = prefab::compile_prefabs(file, &prefabs, &self.component_refs)?;
+        self.factories
+            .insert_resolved(factories, &mut self.objects.symbols)
+            .map_err(|e| prefab::locate_error(file, &prefabs, e))
+    }
+
+    pub fn factories(&self) -> &Factories<'a, T> {
+        &self.factories
+    }
+
+    // names of the components a factory declares, including those declared by its base
+    // factories, see Factory::components
+    pub fn factory_components(&self, type_name: &str) -> Option<Vec<String>> {
+        let index = self.factories.index_of(type_name)?;
+        let flags = self.factories.declared_components(index, &self.component_refs);
+
+        Some(self.component_refs.decode(&flags).iter()
+            .map(|c| c.name().to_string())
+            .collect())
+    }
+
+    pub fn resolved_factories(&self) -> Vec<(String, Vec<String>)> {
+        self.factories.names().into_iter()
+            .map(|name| (name.to_string(), self.factory_components(name).unwrap_or_default()))
+            .collect()
+    }
+
+    // false when the target is not alive, the component is unknown or already present
+    pub fn add_component(&mut self, target: &ObjectIndex, component: &str) -> bool {
+        self.set_component(target, component, true)
+    }
+
+    pub fn remove_component(&mut self, target: &ObjectIndex, component: &str) -> bool {
+        self.set_component(target, component, false)
+    }
+
+    // switches a tag on or off, false when the target is not alive, the tag is unknown or
+    // names a component that carries data, or the tag already was as asked
+    pub fn set_tag(&mut self, target: &ObjectIndex, tag: &str, on: bool) -> bool {
+        match self.component_refs.get(&NameTag::from_str(tag)) {
+            Some(c) if c.is_tag() => self.set_component(target, tag, on),
+            _ => false,
+        }
+    }
+
+    pub fn has_component(&self, target: &ObjectIndex, component: &str) -> bool {
+        match self.component_refs.get(&NameTag::from_str(component)) {
+            Some(c) => self.is_alive(target) && self.entities.pool[*target].bit(*c.index()),
+            None => false,
+        }
+    }
+
+    fn set_component(&mut self, target: &ObjectIndex, component: &str, to: bool) -> bool {
+        match self.component_refs.get(&NameTag::from_str(component)) {
+            Some(c) if self.entities.is_alive(target) => {
+                let changed = self.entities.set_component(*target, *c.index(), to, self.objects.tick);
+                if let Some(spatial) = &mut self.objects.spatial {
+                    spatial.track(*target, &self.entities.pool[*target]);
+                }
+                changed
+            },
+            _ => false,
+        }
+    }
 
-    // pub fn remove_component(&mut self, target: &usize, component: &ComponentIndex) {
-    //     self.entities.pool[*target].set_bit(*component, false);
-    // }
+    // The change tick goes up before every system runs and once more before requests are
+    // handled. Spawns, component changes and Objects::get_mut are stamped with it.
+    pub fn tick(&self) -> u64 {
+        self.objects.tick
+    }
+
+    // alive objects that were borrowed mutably, spawned or gained or lost a component at or
+    // after the tick, changes made outside of update share the tick of the last request handling
+    pub fn changed_since(&self, tick: u64) -> Vec<ObjectIndex> {
+        self.entities.active.iter()
+            .filter(|p| self.objects.changed[**p] >= tick || self.entities.reshaped[**p] >= tick)
+            .copied()
+            .collect()
+    }
 
     pub fn spawn(&mut self, obj_name: &str, type_of: &str) -> Option<ObjectIndex> {
+        self.spawn_with(obj_name, type_of, SpawnArgs::new())
+    }
+
+    // args are available to the factory through BuildTools::arg and BuildTools::arg_value
+    pub fn spawn_with(&mut self, obj_name: &str, type_of: &str, args: SpawnArgs) -> Option<ObjectIndex> {
         create_object(
             NameTag::from_str(obj_name), 
             type_of,
+            &args,
+            &mut self.entities,
+            &mut self.objects,
+            &mut self.factories,
+            &self.component_refs,
+        )
+    }
+
+    // spawned objects are left unnamed
+    pub fn spawn_batch(&mut self, type_of: &str, count: usize) -> Vec<ObjectIndex> {
+        self.spawn_batch_with(type_of, count, SpawnArgs::new())
+    }
+
+    pub fn spawn_batch_with(&mut self, type_of: &str, count: usize, args: SpawnArgs) -> Vec<ObjectIndex> {
+        create_batch(
+            type_of,
+            count,
+            &args,
             &mut self.entities,
             &mut self.objects,
             &mut self.factories,
@@ -195,6 +565,29 @@ impl<'a, T: Default + Debug> Ecs<'a, T> {
         );
     }
 
+    pub fn destroy_batch(&mut self, targets: &[ObjectIndex]) {
+        destroy_batch(
+            targets,
+            &mut self.entities,
+            &mut self.objects,
+        );
+    }
+
+    // destroys every object having all of the given components, returns how many were destroyed
+    pub fn destroy_all_matching(&mut self, components: &[&str]) -> usize {
+        let query = match self.component_refs.flags_of(components) {
+            Some(query) => query,
+            None => return 0,
+        };
+        let targets: Vec<ObjectIndex> = self.entities.active.iter()
+            .filter(|pointer| self.entities.pool[**pointer].contains(&query))
+            .copied()
+            .collect();
+
+        self.destroy_batch(&targets);
+        targets.len()
+    }
+
     pub fn get_mut(&mut self, target: &ObjectIndex) -> &mut T {
         self.objects.get_mut(target)
     }
@@ -203,9 +596,265 @@ impl<'a, T: Default + Debug> Ecs<'a, T> {
         self.objects.get_ref(target)
     }
 
+    // swaps the behaviour of a system while objects and entities stay as they are, 
+    // returns the behaviour that was replaced. Components the new behaviour refers to
+    // that were not defined are left out of its matching, as there is no finalize to report them.
+    pub fn replace_behaviour(&mut self, system: SystemIndex, behaviour: Box<dyn Behaviour<T>>) -> Box<dyn Behaviour<T>> {
+        let _ = self.systems[system].describe(behaviour.as_ref(), &self.component_refs);
+        std::mem::replace(&mut self.behaviours[system], behaviour)
+    }
+
+    // update skips disabled systems, once enabled again a system sees everything that
+    // changed while it was off and its error is cleared. Removals are not kept for disabled
+    // systems, they only see those after they were enabled. Returns false when there is no such system.
+    pub fn set_system_enabled(&mut self, system: SystemIndex, enabled: bool) -> bool {
+        match self.systems.get_mut(system) {
+            Some(system) => { 
+                if enabled && !system.enabled { system.removals_from = self.objects.tick }
+                system.enabled = enabled; 
+                if enabled { system.error = None }
+                true 
+            },
+            None => false,
+        }
+    }
+
+    pub fn system_enabled(&self, system: SystemIndex) -> bool {
+        self.systems.get(system).is_some_and(|s| s.enabled)
+    }
+
+    // the error a system disabled itself with, see System::fail
+    pub fn system_error(&self, system: SystemIndex) -> Option<&str> {
+        self.systems.get(system).and_then(|s| s.error())
+    }
+
     pub fn find(&self, name: &str) -> Option<ObjectIndex> {
         self.objects.find(name)
     }
+
+    pub fn find_all(&self, name: &str) -> Vec<ObjectIndex> {
+        self.objects.find_all(name)
+    }
+
+    // fails when the target is not alive or the name is taken under NamePolicy::Reject
+    pub fn rename(&mut self, target: &ObjectIndex, name: &str) -> bool {
+        if !self.entities.is_alive(target) {
+            return false;
+        }
+        let dense = self.entities.sparse[*target];
+        if self.objects.symbols.resolve(self.objects.active[dense].1) == name {
+            return true;
+        }
+        if !self.objects.name_available(name) {
+            return false;
+        }
+        let old = std::mem::replace(&mut self.objects.active[dense].1, Symbol::EMPTY);
+        self.objects.unindex_name(*target, old);
+        self.objects.symbols.release(old);
+        let symbol = self.objects.symbols.acquire(name);
+        set_name(*target, symbol, &self.entities, &mut self.objects);
+        true
+    }
+
+    pub fn name_of(&self, target: &ObjectIndex) -> Option<&str> {
+        if !self.entities.is_alive(target) {
+            return None;
+        }
+        Some(self.objects.symbols.resolve(self.objects.active[self.entities.sparse[*target]].1))
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.objects.symbols
+    }
+
+    pub fn has<K: ComponentKey>(&self, target: &ObjectIndex) -> bool {
+        match self.component_refs.of::<K>() {
+            Some(component) => self.is_alive(target) && self.entities.pool[*target].bit(*component.index()),
+            None => false,
+        }
+    }
+
+    pub fn is_alive(&self, target: &ObjectIndex) -> bool {
+        self.entities.is_alive(target)
+    }
+
+    pub fn capacity(&self) -> CapacityReport {
+        self.entities.report()
+    }
+
+    // Spawns every object of a scene file through the registered factories, see
+    // parse_scene for the format. When one of them can't be spawned none remain.
+    pub fn spawn_scene<P: AsRef<Path>>(&mut self, path: P) -> Result<SceneId, SceneError> {
+        let (file, source) = scene::read_scene(path)?;
+        self.spawn_scene_from(&file, &source)
+    }
+
+    pub fn spawn_scene_from(&mut self, file: &str, source: &str) -> Result<SceneId, SceneError> {
+        let entries = scene::parse_scene(file, source)?;
+        if let Some(entry) = entries.iter().find(|e| self.factories.index_of(&e.factory).is_none()) {
+            return Err(SceneError::new(file, entry.line, format!("unknown factory '{}'", entry.factory)));
+        }
+
+        let scene = self.new_scene();
+        let mut spawned = Vec::with_capacity(entries.len());
+        for entry in &entries {
+            let mut args = SpawnArgs::new();
+            for (key, value) in &entry.args {
+                args.set(key, value.clone());
+            }
+            match self.spawn_with(&entry.name, &entry.factory, args) {
+                Some(target) => {
+                    self.entities.scenes[target] = scene.0;
+                    spawned.push(target);
+                },
+                None => {
+                    self.destroy_batch(&spawned);
+                    return Err(SceneError::new(file, entry.line, match entry.name.as_str() {
+                        "" => "the world is full".to_string(),
+                        name => format!("the world is full or the name '{}' is taken", name),
+                    }));
+                },
+            }
+        }
+        Ok(scene)
+    }
+
+    // an empty scene, objects join it through set_scene
+    pub fn new_scene(&mut self) -> SceneId {
+        let scene = SceneId(self.entities.next_scene);
+        self.entities.next_scene += 1;
+        scene
+    }
+
+    // an object is part of one scene at most, None takes it out of its scene
+    pub fn set_scene(&mut self, target: &ObjectIndex, scene: Option<SceneId>) -> bool {
+        if !self.entities.is_alive(target) {
+            return false;
+        }
+        self.entities.scenes[*target] = scene.map_or(0, |s| s.0);
+        true
+    }
+
+    pub fn scene_of(&self, target: &ObjectIndex) -> Option<SceneId> {
+        match self.entities.is_alive(target) {
+            true if self.entities.scenes[*target] != 0 => Some(SceneId(self.entities.scenes[*target])),
+            _ => None,
+        }
+    }
+
+    pub fn scene_members(&self, scene: SceneId) -> Vec<ObjectIndex> {
+        let mut members: Vec<ObjectIndex> = self.entities.active.iter()
+            .filter(|p| self.entities.scenes[**p] == scene.0)
+            .copied()
+            .collect();
+        members.sort_unstable();
+        members
+    }
+
+    // destroys all objects of the scene in one batch, objects of other scenes and their
+    // handles are left alone. Returns the number of objects destroyed.
+    pub fn unload_scene(&mut self, scene: SceneId) -> usize {
+        let members = self.scene_members(scene);
+        self.destroy_batch(&members);
+        members.len()
+    }
+
+    // see Objects::query_radius
+    pub fn query_radius(&mut self, center: (f32, f32), radius: f32) -> Vec<ObjectIndex> {
+        self.objects.query_radius(center, radius)
+    }
+
+    pub fn query_aabb(&mut self, min: (f32, f32), max: (f32, f32)) -> Vec<ObjectIndex> {
+        self.objects.query_aabb(min, max)
+    }
+
+    pub fn query_nearest(&mut self, point: (f32, f32), count: usize) -> Vec<ObjectIndex> {
+        self.objects.query_nearest(point, count)
+    }
+
+    // Moves the object with its data, name and components to another world with the
+    // same component names and returns its handle there
+    pub fn move_entity_to(&mut self, other: &mut Ecs<T>, target: &ObjectIndex) -> Result<ObjectIndex, TransferError> {
+        let map = self.move_entities_to(other, &[*target], |_, _| {})?;
+        Ok(map.get(target).unwrap())
+    }
+
+    // Moves all targets or none of them. Handles the objects hold to each other are
+    // for the caller to fix, remap gets every moved object with the old to new mapping.
+    pub fn move_entities_to<F>(&mut self, other: &mut Ecs<T>, targets: &[ObjectIndex], remap: F) -> Result<EntityMap, TransferError>
+    where F: FnMut(&mut T, &EntityMap) {
+        transfer::move_entities(self, other, targets, remap)
+    }
+
+    // Hash of the tick and every alive object: slot, generation, components, name and
+    // the Debug output of T. Two worlds that went through the same commands have the
+    // same checksum, as long as Debug of T prints the same for equal objects.
+    pub fn checksum(&self) -> u64 {
+        let mut checksum = Checksum::new();
+        checksum.write_u64(self.objects.tick);
+        checksum.write_u64(self.entities.pool.len() as u64);
+
+        let mut active = self.entities.active.clone();
+        active.sort_unstable();
+        for pointer in active {
+            checksum.write_u64(pointer as u64);
+            checksum.write_u64(self.entities.generations[pointer] as u64);
+            checksum.write_u64(self.entities.pool[pointer].0 as u64);
+            checksum.write(self.name_of(&pointer).unwrap_or_default().as_bytes());
+            let _ = write!(checksum, "{:?}", self.objects.pool[pointer]);
+        }
+        checksum.0
+    }
+}
+
+impl<'a, T: Default + Debug + Reflect> Ecs<'a, T> {
+
+    // Runs a command, see Recorder for keeping them. Returns the objects spawned.
+    pub fn execute(&mut self, command: &Command) -> Result<Vec<ObjectIndex>, CommandError> {
+        let alive = |ecs: &Self, target: &ObjectIndex| match ecs.is_alive(target) {
+            true => Ok(()),
+            false => Err(CommandError::NotAlive(*target)),
+        };
+        match command {
+            Command::Start => self.start(),
+            Command::Update(dt) => self.update_dt(*dt),
+            Command::Spawn { name, factory } => return Ok(self.spawn(name, factory).into_iter().collect()),
+            Command::SpawnWith { name, factory, args } => {
+                let mut spawn_args = SpawnArgs::new();
+                for (key, value) in args {
+                    spawn_args.set(key, value.clone());
+                }
+                return Ok(self.spawn_with(name, factory, spawn_args).into_iter().collect());
+            },
+            Command::SpawnScene { file, source } => {
+                let scene = self.spawn_scene_from(file, source).map_err(CommandError::Scene)?;
+                return Ok(self.scene_members(scene));
+            },
+            Command::UnloadScene(scene) => { self.unload_scene(*scene); },
+            Command::SpawnBatch { factory, count } => return Ok(self.spawn_batch(factory, *count)),
+            Command::Destroy(target) => {
+                alive(self, target)?;
+                self.destroy(target);
+            },
+            Command::Rename(target, name) => {
+                alive(self, target)?;
+                self.rename(target, name);
+            },
+            Command::AddComponent(target, component) => {
+                alive(self, target)?;
+                self.add_component(target, component);
+            },
+            Command::RemoveComponent(target, component) => {
+                alive(self, target)?;
+                self.remove_component(target, component);
+            },
+            Command::Set { target, path, value } => {
+                alive(self, target)?;
+                self.get_mut(target).set_path(path, value.clone()).map_err(CommandError::Field)?;
+            },
+        }
+        Ok(Vec::new())
+    }
 }
 
 // DRY implementations 
@@ -213,37 +862,135 @@ impl<'a, T: Default + Debug> Ecs<'a, T> {
 fn create_object<T: Default> (
     obj_name: NameTag, 
     type_name: &str,
+    args: &SpawnArgs,
 
     entities: &mut Entities,
     objects: &mut Objects<T>,
-    factories: &mut Vec<(String, Box<dyn Factory<T>>)>,
+    factories: &mut Factories<T>,
     component_refs: &ComponentRefs,
 
 ) -> Option<ObjectIndex> {
 
+    if !objects.name_available(obj_name.as_str()) {
+        return None;
+    }
+    reserve_slots(1, entities, objects);
+
     if let Some(pointer) = entities.free.pop() {
+        let name = objects.symbols.acquire(obj_name.as_str());
+        let factory = factory_of(type_name, objects, factories);
+        init_object(pointer, name, factory, args, entities, objects, factories, component_refs);
+        return Some(pointer);
+    }
+    None
+}
 
-        entities.active.push(pointer);
-        objects.active.push((pointer, obj_name.clone()));
-        entities.pool[pointer].reset();
+// looks the factory up once and spawns as many objects as there are free slots for
+fn create_batch<T: Default> (
+    type_name: &str,
+    count: usize,
+    args: &SpawnArgs,
 
-        // for comp in components {
-        //     if let Some(c) = component_refs.get(&comp) {
-        //         entities.pool[pointer].set_bit(*c.index(), true)
-        //     }
-        // }
-        // return Some(pointer);
+    entities: &mut Entities,
+    objects: &mut Objects<T>,
+    factories: &mut Factories<T>,
+    component_refs: &ComponentRefs,
+
+) -> Vec<ObjectIndex> {
+
+    reserve_slots(count, entities, objects);
+
+    let factory = factory_of(type_name, objects, factories);
+    let count = count.min(entities.free.len());
+    let mut spawned = Vec::with_capacity(count);
 
-        if let Some(factory) = factories.iter_mut().find(|f| f.0 == *type_name) {
-            let mut build_tools = BuildTools::new( 
-                &mut objects.pool[pointer],
-                &component_refs,
-                &mut entities.pool[pointer]
-            );
-            factory.1.make_spawn(&mut build_tools);
+    entities.active.reserve(count);
+    objects.active.reserve(count);
+
+    for _ in 0..count {
+        if let Some(pointer) = entities.free.pop() {
+            init_object(pointer, Symbol::EMPTY, factory, args, entities, objects, factories, component_refs);
+            spawned.push(pointer);
         }
     }
-    None
+    spawned
+}
+
+fn factory_of<T: Default> (
+    type_name: &str,
+
+    objects: &Objects<T>,
+    factories: &Factories<T>,
+) -> Option<usize> {
+    objects.symbols.get(type_name).and_then(|symbol| factories.index_of_symbol(symbol))
+}
+
+// grows the pools according to the growth policy when there are not enough free slots
+fn reserve_slots<T: Default> (
+    count: usize,
+
+    entities: &mut Entities,
+    objects: &mut Objects<T>,
+) {
+    if entities.free.len() >= count {
+        return;
+    }
+    let capacity = entities.pool.len();
+    let required = entities.active.len() + count;
+    let new_size = entities.growth.grow_to(capacity, required);
+
+    if new_size > capacity {
+        entities.grow(new_size);
+        objects.grow(new_size);
+    }
+}
+
+// names an alive object, objects losing the name to it under NamePolicy::Replace become unnamed
+fn set_name<T: Default> (
+    pointer: ObjectIndex,
+    name: Symbol,
+
+    entities: &Entities,
+    objects: &mut Objects<T>,
+) {
+    for displaced in objects.index_name(pointer, name) {
+        objects.active[entities.sparse[displaced]].1 = Symbol::EMPTY;
+        objects.symbols.release(name);
+    }
+    objects.active[entities.sparse[pointer]].1 = name;
+}
+
+#[allow(clippy::too_many_arguments)]
+fn init_object<T: Default> (
+    pointer: ObjectIndex,
+    obj_name: Symbol,
+    factory: Option<usize>,
+    args: &SpawnArgs,
+
+    entities: &mut Entities,
+    objects: &mut Objects<T>,
+    factories: &mut Factories<T>,
+    component_refs: &ComponentRefs,
+) {
+    entities.activate(pointer);
+    objects.active.push((pointer, Symbol::EMPTY));
+    objects.pool[pointer] = T::default();
+    set_name(pointer, obj_name, entities, objects);
+
+    if let Some(factory) = factory {
+        let mut build_tools = BuildTools::with_args( 
+            &mut objects.pool[pointer],
+            component_refs,
+            &mut entities.pool[pointer],
+            args,
+        );
+        factories.make_spawn(factory, &mut build_tools);
+    }
+    objects.changed[pointer] = objects.tick;
+    entities.stamp_added(pointer, objects.tick);
+    if let Some(spatial) = &mut objects.spatial {
+        spatial.track(pointer, &entities.pool[pointer]);
+    }
 }
 
 fn destroy_object<T: Default> (
@@ -252,9 +999,23 @@ fn destroy_object<T: Default> (
     entities: &mut Entities,
     objects: &mut Objects<T>,
 ) {
-    if let Some(i) = entities.active.iter().position(|pointer| pointer == target) {
-        entities.active.remove(i);
-        objects.active.remove(i);
-        entities.free.push(i);
+    if let Some(dense) = entities.deactivate(target, objects.tick) {
+        let (_, name) = objects.active.swap_remove(dense);
+        objects.unindex_name(*target, name);
+        objects.symbols.release(name);
+        if let Some(spatial) = &mut objects.spatial {
+            spatial.track(*target, &entities.pool[*target]);
+        }
+    }
+}
+
+fn destroy_batch<T: Default> (
+    targets: &[ObjectIndex],
+
+    entities: &mut Entities,
+    objects: &mut Objects<T>,
+) {
+    for target in targets {
+        destroy_object(target, entities, objects);
     }
 }
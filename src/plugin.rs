@@ -0,0 +1,82 @@
+use std::any::type_name;
+use std::mem::{ size_of, align_of };
+
+use super::Behaviour;
+
+// Bumped whenever PluginDeclaration or the way behaviours are handed over changes
+pub const PLUGIN_ABI_VERSION: u32 = 2;
+pub const ECS_VERSION: &str = env!("CARGO_PKG_VERSION");
+pub const RUSTC_VERSION: &str = env!("SIMPLE_ECS_RUSTC_VERSION");
+
+
+// The only symbol a behaviour plugin exports, see export_behaviours!. Everything
+// but abi_version is only read after abi_version matched.
+#[repr(C)]
+pub struct PluginDeclaration {
+    pub abi_version: u32,
+    pub ecs_version: &'static str,
+    pub rustc_version: &'static str,
+    pub object_layout: fn() -> ObjectLayout,
+    // receives a *mut PluginRegistrar<T> once the object layout has been checked
+    pub register: unsafe fn(*mut ()),
+}
+
+// Identifies the object type T a plugin was built for
+#[derive(Debug, Clone, PartialEq)]
+pub struct ObjectLayout {
+    pub name: &'static str,
+    pub size: usize,
+    pub align: usize,
+}
+
+impl ObjectLayout {
+    pub fn of<T>() -> Self {
+        ObjectLayout { name: type_name::<T>(), size: size_of::<T>(), align: align_of::<T>() }
+    }
+}
+
+
+pub struct PluginRegistrar<T: Default> {
+    pub(crate) behaviours: Vec<(String, Box<dyn Behaviour<T>>)>,
+}
+
+impl<T: Default> Default for PluginRegistrar<T> {
+    fn default() -> Self {
+        PluginRegistrar { behaviours: Vec::new() }
+    }
+}
+
+impl<T: Default> PluginRegistrar<T> {
+    // the name ties the behaviour to its system across reloads
+    pub fn add(&mut self, name: &str, behaviour: Box<dyn Behaviour<T>>) {
+        self.behaviours.push((name.to_string(), behaviour));
+    }
+}
+
+
+// Exports the behaviours of a cdylib crate for hot reloading:
+//
+//   export_behaviours!(Cell, |registrar| {
+//       registrar.add("mover", Box::new(Mover));
+//   });
+//
+#[macro_export]
+macro_rules! export_behaviours {
+    ($object:ty, $register:expr) => {
+        #[no_mangle]
+        pub static SIMPLE_ECS_PLUGIN: $crate::PluginDeclaration = $crate::PluginDeclaration {
+            abi_version: $crate::PLUGIN_ABI_VERSION,
+            ecs_version: $crate::ECS_VERSION,
+            rustc_version: $crate::RUSTC_VERSION,
+            object_layout: $crate::ObjectLayout::of::<$object>,
+            register: {
+                unsafe fn register(registrar: *mut ()) {
+                    let registrar = &mut *(registrar as *mut $crate::PluginRegistrar<$object>);
+                    let register: fn(&mut $crate::PluginRegistrar<$object>) = $register;
+                    register(registrar);
+                }
+                register
+            },
+        };
+    };
+}
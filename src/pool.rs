@@ -1,21 +1,95 @@
 use std::fmt::Debug;
-use std::convert::TryInto;
+use std::collections::HashMap;
 
 use super::{
     //MAX_OBJECTS,
     ObjectIndex,
-    NameTag,
+    ComponentIndex,
     BitFlags,
+    Symbol,
+    Symbols,
+    ComponentRefs,
 };
+use super::spatial::SpatialIndex;
 
+// sparse slot for entities that are not alive
+pub(crate) const INACTIVE: usize = usize::MAX;
+
+// most removal events kept, the oldest are dropped past it
+pub(crate) const MAX_REMOVED: usize = 1 << 16;
+
+// What happens when a spawn finds no free slots left
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum GrowthPolicy {
+    #[default]
+    Fixed,                              // spawning fails, the default
+    Double,                             // capacity doubles, without limit
+    Chunked { chunk: usize, max: usize }, // grows by whole chunks, never beyond max
+}
+
+impl GrowthPolicy {
+    // capacity to grow to so that `required` slots fit, as far as the policy allows
+    pub fn grow_to(&self, capacity: usize, required: usize) -> usize {
+        if required <= capacity {
+            return capacity;
+        }
+        match *self {
+            GrowthPolicy::Fixed => capacity,
+            GrowthPolicy::Double => {
+                let mut grown = capacity.max(1);
+                while grown < required { grown *= 2; }
+                grown
+            },
+            GrowthPolicy::Chunked { chunk, max } => {
+                if chunk == 0 || capacity >= max {
+                    return capacity;
+                }
+                let chunks = (required - capacity).div_ceil(chunk);
+                (capacity + chunks * chunk).min(max)
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CapacityReport {
+    pub capacity: usize,
+    pub active: usize,
+    pub free: usize,
+    pub high_water_mark: usize, // most objects ever alive at the same time
+    pub times_grown: usize,
+    pub growth: GrowthPolicy,
+}
+
+// Entities are kept as a sparse set: `sparse` maps every pool slot to its 
+// position in the dense `active` list, so both can be updated in O(1).
 pub struct Entities {
     pub(crate) pool: Vec<BitFlags>, //[BitFlags; MAX_OBJECTS],
+    pub(crate) sparse: Vec<usize>,
     pub(crate) active: Vec<ObjectIndex>,
     pub(crate) free: Vec<ObjectIndex>,
+    pub(crate) growth: GrowthPolicy,
+    pub(crate) high_water_mark: usize,
+    pub(crate) times_grown: usize,
+    // change detection, see Ecs::tick
+    pub(crate) stride: usize, // components tracked per slot, tags are not
+    pub(crate) columns: Vec<Option<usize>>, // column in `added` of every component, None for tags
+    pub(crate) added: Vec<u64>, // tick every component of a slot was added at
+    pub(crate) reshaped: Vec<u64>, // tick a slot last gained or lost a component
+    pub(crate) removed: Vec<(ObjectIndex, ComponentIndex, u64)>,
+    pub(crate) generations: Vec<u32>, // times every slot was spawned into, tells reused slots apart
+    pub(crate) scenes: Vec<u32>, // scene id of every slot, 0 when it isn't part of one
+    pub(crate) next_scene: u32,
 }
 
 impl Entities {
-    pub(crate) fn new(size: usize) -> Self {
+    pub(crate) fn new(size: usize, growth: GrowthPolicy, component_refs: &ComponentRefs) -> Self {
+        let mut stride = 0;
+        let columns: Vec<Option<usize>> = component_refs.list().iter().map(|c| match c.is_tag() {
+            true => None,
+            false => { stride += 1; Some(stride - 1) },
+        }).collect();
+
         let mut create_entities = Vec::<BitFlags>::with_capacity(size);
         create_entities.resize_with(size, Default::default);
 
@@ -24,30 +98,213 @@ impl Entities {
 
         Entities { 
             pool: create_entities, //create_entities.try_into().unwrap(),
+            sparse: vec![INACTIVE; size],
             active: Vec::with_capacity(size),
             free,
+            growth,
+            high_water_mark: 0,
+            times_grown: 0,
+            stride,
+            columns,
+            added: vec![0; size * stride],
+            reshaped: vec![0; size],
+            removed: Vec::new(),
+            generations: vec![0; size],
+            scenes: vec![0; size],
+            next_scene: 1,
+        }
+    }
+
+    // new slots are appended, so existing handles stay valid
+    pub(crate) fn grow(&mut self, new_size: usize) {
+        let size = self.pool.len();
+        if new_size <= size {
+            return;
+        }
+        self.pool.resize_with(new_size, Default::default);
+        self.sparse.resize(new_size, INACTIVE);
+        self.added.resize(new_size * self.stride, 0);
+        self.reshaped.resize(new_size, 0);
+        self.generations.resize(new_size, 0);
+        self.scenes.resize(new_size, 0);
+        self.free.extend(size..new_size);
+        self.times_grown += 1;
+    }
+
+    pub fn report(&self) -> CapacityReport {
+        CapacityReport {
+            capacity: self.pool.len(),
+            active: self.active.len(),
+            free: self.free.len(),
+            high_water_mark: self.high_water_mark,
+            times_grown: self.times_grown,
+            growth: self.growth,
+        }
+    }
+
+    pub fn is_alive(&self, target: &ObjectIndex) -> bool {
+        self.sparse.get(*target).is_some_and(|d| *d != INACTIVE)
+    }
+
+    pub(crate) fn activate(&mut self, pointer: ObjectIndex) {
+        self.sparse[pointer] = self.active.len();
+        self.active.push(pointer);
+        self.pool[pointer].reset();
+        self.generations[pointer] = self.generations[pointer].wrapping_add(1);
+        self.scenes[pointer] = 0;
+        self.high_water_mark = self.high_water_mark.max(self.active.len());
+    }
+
+    // swap-removes the target from the dense list and returns the dense position 
+    // it had, so the parallel list in Objects can be swap-removed the same way
+    pub(crate) fn deactivate(&mut self, target: &ObjectIndex, tick: u64) -> Option<usize> {
+        if !self.is_alive(target) {
+            return None;
+        }
+        for component in 0..self.columns.len() {
+            if self.pool[*target].bit(component) {
+                self.push_removed(*target, component, tick);
+            }
+        }
+        let dense = self.sparse[*target];
+        self.active.swap_remove(dense);
+        if let Some(moved) = self.active.get(dense) {
+            self.sparse[*moved] = dense;
+        }
+        self.sparse[*target] = INACTIVE;
+        self.pool[*target].reset();
+        self.free.push(*target);
+        Some(dense)
+    }
+}
+
+// component bookkeeping for change detection
+impl Entities {
+    // returns false when the component was already set as asked
+    pub(crate) fn set_component(&mut self, target: ObjectIndex, component: ComponentIndex, to: bool, tick: u64) -> bool {
+        if self.pool[target].bit(component) == to {
+            return false;
+        }
+        self.pool[target].set_bit(component, to);
+        self.reshaped[target] = tick;
+        match (to, self.columns[component]) {
+            (true, Some(column)) => self.added[target * self.stride + column] = tick,
+            (true, None) => {},
+            (false, _) => self.push_removed(target, component, tick),
+        }
+        true
+    }
+
+    // marks every component a factory gave a freshly spawned entity as added
+    pub(crate) fn stamp_added(&mut self, target: ObjectIndex, tick: u64) {
+        for (component, column) in self.columns.iter().enumerate() {
+            if let Some(column) = column {
+                if self.pool[target].bit(component) {
+                    self.added[target * self.stride + column] = tick;
+                }
+            }
+        }
+        self.reshaped[target] = tick;
+    }
+
+    // tags keep no tick of their own, for them any change of components since counts
+    pub(crate) fn added_since(&self, target: ObjectIndex, component: ComponentIndex, tick: u64) -> bool {
+        match self.columns[component] {
+            Some(column) => self.added[target * self.stride + column] > tick,
+            None => self.reshaped[target] > tick,
+        }
+    }
+
+    // removal events are kept until every enabled system watching removals had the chance
+    // to see them, at most MAX_REMOVED of them so a world that isn't updated stays bounded
+    pub(crate) fn trim_removed(&mut self, seen: u64) {
+        self.removed.retain(|r| r.2 > seen);
+    }
+
+    fn push_removed(&mut self, target: ObjectIndex, component: ComponentIndex, tick: u64) {
+        if self.removed.len() == MAX_REMOVED {
+            self.removed.drain(..MAX_REMOVED / 4);
+        }
+        self.removed.push((target, component, tick));
+    }
+}
+
+// How the name index treats a name that is already in use
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum NamePolicy {
+    Reject,          // spawning or renaming to a used name fails
+    Replace,         // the new object takes the name, previous owners become unnamed
+    AllowDuplicates, // any number of objects share the name, see Objects::find_all
+}
+
+pub(crate) struct NameIndex {
+    pub(crate) policy: NamePolicy,
+    map: HashMap<Symbol, Vec<ObjectIndex>>,
+}
+
+impl NameIndex {
+    pub(crate) fn new(policy: NamePolicy) -> Self {
+        NameIndex { policy, map: HashMap::new() }
+    }
+
+    // returns previous owners that lost the name
+    fn insert(&mut self, pointer: ObjectIndex, name: Symbol) -> Vec<ObjectIndex> {
+        let owners = self.map.entry(name).or_default();
+        match self.policy {
+            NamePolicy::Replace => std::mem::replace(owners, vec![pointer]),
+            _ => { owners.push(pointer); Vec::new() },
+        }
+    }
+
+    fn remove(&mut self, pointer: ObjectIndex, name: Symbol) {
+        if let Some(owners) = self.map.get_mut(&name) {
+            owners.retain(|o| *o != pointer);
+            if owners.is_empty() {
+                self.map.remove(&name);
+            }
         }
     }
 }
 
 pub struct Objects<T: Default> {
     pub(crate) pool: Vec<T>, //>[T; MAX_OBJECTS],
-    pub(crate) active: Vec<(ObjectIndex, NameTag)>,
+    pub(crate) active: Vec<(ObjectIndex, Symbol)>,
+    pub(crate) names: Option<NameIndex>,
+    pub(crate) symbols: Symbols, // interned component, factory and object names
+    pub(crate) tick: u64, // current change tick, see Ecs::tick
+    pub(crate) dt: f32, // frame time of the current update, see Ecs::update_dt
+    pub(crate) changed: Vec<u64>, // tick every object was last borrowed mutably at
+    pub(crate) components: usize,
+    pub(crate) writing: BitFlags, // components a mutable borrow changes, all of them outside of systems
+    pub(crate) written: Vec<u64>, // tick every component of an object was last changed at
+    pub(crate) spatial: Option<SpatialIndex<T>>,
 }
 
 impl<T: Default + Debug> Objects<T> {
 
-    pub(crate) fn new(size: usize) -> Self {
+    pub(crate) fn new(size: usize, components: usize, names: Option<NamePolicy>, symbols: Symbols) -> Self {
         let mut create_objects = Vec::<T>::with_capacity(size);
         create_objects.resize_with(size, Default::default);
 
         Objects { 
             pool: create_objects, //create_objects.try_into().unwrap(),
             active: Vec::new(),
+            names: names.map(NameIndex::new),
+            symbols,
+            tick: 1, // systems that never ran have a last run of 0
+            dt: 0.0,
+            changed: vec![0; size],
+            components,
+            writing: BitFlags::all(components),
+            written: vec![0; size * components],
+            spatial: None,
         }
     }
 
+    // marks the object as changed, use get_ref when only reading. Within a system only
+    // the components it writes count as changed, see Access
     pub fn get_mut(&mut self, target: &ObjectIndex) -> &mut T {
+        self.mark_changed(*target);
         &mut self.pool[*target]
     }
 
@@ -55,8 +312,111 @@ impl<T: Default + Debug> Objects<T> {
         &self.pool[*target]
     }
 
+    // seconds the current frame stands for, as given to Ecs::update_dt
+    pub fn dt(&self) -> f32 {
+        self.dt
+    }
+
     pub fn find(&self, name: &str) -> Option<ObjectIndex> {
-        let tag = NameTag::from_str(name);
-        self.active.iter().find(|x| x.1 == tag).map(|a| a.0)
+        // a name that was never interned can't belong to any object
+        let symbol = self.symbols.get(name)?;
+        match &self.names {
+            Some(index) => index.map.get(&symbol).and_then(|owners| owners.first().copied()),
+            None => self.active.iter().find(|x| x.1 == symbol).map(|a| a.0),
+        }
+    }
+
+    pub fn find_all(&self, name: &str) -> Vec<ObjectIndex> {
+        let symbol = match self.symbols.get(name) {
+            Some(symbol) => symbol,
+            None => return Vec::new(),
+        };
+        match &self.names {
+            Some(index) => index.map.get(&symbol).cloned().unwrap_or_default(),
+            None => self.active.iter().filter(|x| x.1 == symbol).map(|a| a.0).collect(),
+        }
+    }
+
+    pub fn symbols(&self) -> &Symbols {
+        &self.symbols
+    }
+
+    // Neighbour queries over the objects holding the position component, see
+    // SystemBuilder::with_spatial_index. Results are in index order, except for
+    // query_nearest which gives the closest first. Empty without a spatial index.
+    pub fn query_radius(&mut self, center: (f32, f32), radius: f32) -> Vec<ObjectIndex> {
+        self.spatial().map_or_else(Vec::new, |s| s.radius(center, radius))
+    }
+
+    pub fn query_aabb(&mut self, min: (f32, f32), max: (f32, f32)) -> Vec<ObjectIndex> {
+        self.spatial().map_or_else(Vec::new, |s| s.aabb(min, max))
+    }
+
+    pub fn query_nearest(&mut self, point: (f32, f32), count: usize) -> Vec<ObjectIndex> {
+        self.spatial().map_or_else(Vec::new, |s| s.nearest(point, count))
+    }
+
+    pub fn has_spatial_index(&self) -> bool {
+        self.spatial.is_some()
+    }
+
+    fn spatial(&mut self) -> Option<&SpatialIndex<T>> {
+        let spatial = self.spatial.as_mut()?;
+        spatial.sync(&self.pool);
+        Some(spatial)
+    }
+}
+
+impl<T: Default> Objects<T> {
+    pub(crate) fn mark_changed(&mut self, target: ObjectIndex) {
+        self.changed[target] = self.tick;
+        let row = target * self.components;
+        for component in 0..self.components {
+            if self.writing.bit(component) {
+                self.written[row + component] = self.tick;
+            }
+        }
+        if let Some(spatial) = &mut self.spatial {
+            spatial.mark(target);
+        }
+    }
+
+    pub(crate) fn changed_since(&self, target: ObjectIndex, component: ComponentIndex, tick: u64) -> bool {
+        self.written[target * self.components + component] > tick
+    }
+
+    pub(crate) fn grow(&mut self, new_size: usize) {
+        if new_size > self.pool.len() {
+            self.pool.resize_with(new_size, Default::default);
+            self.changed.resize(new_size, 0);
+            self.written.resize(new_size * self.components, 0);
+            if let Some(spatial) = &mut self.spatial {
+                spatial.grow(new_size);
+            }
+        }
+    }
+}
+
+// name bookkeeping, unnamed objects are never indexed
+impl<T: Default> Objects<T> {
+    pub(crate) fn name_available(&self, name: &str) -> bool {
+        match (&self.names, self.symbols.get(name)) {
+            (Some(index), Some(symbol)) if index.policy == NamePolicy::Reject && symbol != Symbol::EMPTY => 
+                !index.map.contains_key(&symbol),
+            _ => true,
+        }
+    }
+
+    pub(crate) fn index_name(&mut self, pointer: ObjectIndex, name: Symbol) -> Vec<ObjectIndex> {
+        match &mut self.names {
+            Some(index) if name != Symbol::EMPTY => index.insert(pointer, name),
+            _ => Vec::new(),
+        }
+    }
+
+    pub(crate) fn unindex_name(&mut self, pointer: ObjectIndex, name: Symbol) {
+        if let Some(index) = &mut self.names {
+            index.remove(pointer, name);
+        }
     }
 }
@@ -0,0 +1,196 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use super::{ NameTag, ComponentRefs, Factory, FactoryEntry, FactoryError, BuildTools };
+
+// Objects spawned from prefab files need a way to receive field values by name.
+pub trait PrefabData {
+    fn set_field(&mut self, field: &str, value: &str) -> Result<(), String>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PrefabError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl PrefabError {
+    fn new(file: &str, line: usize, message: String) -> Self {
+        PrefabError { file: file.to_string(), line, message }
+    }
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for PrefabError {}
+
+
+// A single parsed prefab section:
+//
+//   [orc]
+//   components = walker, attacker
+//   health = 10
+//
+//   [orc_archer]
+//   extends = orc
+//   components = ranged
+//   health = 8
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct Prefab {
+    pub type_name: String,
+    pub extends: Option<(String, usize)>,
+    pub components: Vec<(String, usize)>,
+    pub fields: Vec<(String, String, usize)>,
+    pub line: usize,
+}
+
+pub fn parse_prefabs(file: &str, source: &str) -> Result<Vec<Prefab>, PrefabError> {
+    let mut prefabs: Vec<Prefab> = Vec::new();
+
+    for (i, raw) in source.lines().enumerate() {
+        let line = i + 1;
+        let text = raw.trim();
+
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        if text.starts_with('[') {
+            if !text.ends_with(']') {
+                return Err(PrefabError::new(file, line, format!("unterminated section header '{}'", text)));
+            }
+            let type_name = text[1..text.len() - 1].trim();
+            if type_name.is_empty() {
+                return Err(PrefabError::new(file, line, "empty prefab name".to_string()));
+            }
+            if let Some(other) = prefabs.iter().find(|p| p.type_name == type_name) {
+                return Err(PrefabError::new(file, line, format!(
+                    "prefab '{}' is already defined on line {}", type_name, other.line
+                )));
+            }
+            prefabs.push(Prefab {
+                type_name: type_name.to_string(),
+                extends: None,
+                components: Vec::new(),
+                fields: Vec::new(),
+                line,
+            });
+            continue;
+        }
+
+        let prefab = match prefabs.last_mut() {
+            Some(p) => p,
+            None => return Err(PrefabError::new(file, line, "entry outside of a [prefab] section".to_string())),
+        };
+
+        let (key, value) = match text.find('=') {
+            Some(at) => (text[..at].trim(), text[at + 1..].trim()),
+            None => return Err(PrefabError::new(file, line, format!("expected 'key = value', found '{}'", text))),
+        };
+        if key.is_empty() {
+            return Err(PrefabError::new(file, line, "missing key before '='".to_string()));
+        }
+
+        if key == "extends" {
+            if let Some((_, first)) = &prefab.extends {
+                return Err(PrefabError::new(file, line, format!(
+                    "prefab '{}' already extends a factory on line {}", prefab.type_name, first
+                )));
+            }
+            if value.is_empty() {
+                return Err(PrefabError::new(file, line, "missing factory name after 'extends'".to_string()));
+            }
+            prefab.extends = Some((value.to_string(), line));
+        } else if key == "components" {
+            for name in value.split(',').map(|n| n.trim()).filter(|n| !n.is_empty()) {
+                prefab.components.push((name.to_string(), line));
+            }
+        } else {
+            prefab.fields.push((key.to_string(), value.to_string(), line));
+        }
+    }
+    Ok(prefabs)
+}
+
+pub fn read_prefabs<P: AsRef<Path>>(path: P) -> Result<(String, String), PrefabError> {
+    let file = path.as_ref().display().to_string();
+    match fs::read_to_string(path.as_ref()) {
+        Ok(source) => Ok((file, source)),
+        Err(e) => Err(PrefabError::new(&file, 0, format!("could not read file: {}", e))),
+    }
+}
+
+// Turns parsed prefabs into factories, checking component names and field
+// values up front so mistakes surface at load time instead of at spawn time.
+pub(crate) fn compile_prefabs<'a, T: Default + PrefabData + 'static>(
+    file: &str,
+    prefabs: &[Prefab],
+    component_refs: &ComponentRefs,
+) -> Result<Vec<FactoryEntry<'a, T>>, PrefabError> {
+
+    let mut factories: Vec<FactoryEntry<'a, T>> = Vec::new();
+
+    for prefab in prefabs {
+        for (name, line) in &prefab.components {
+            if component_refs.get(&NameTag::from_str(name)).is_none() {
+                return Err(PrefabError::new(file, *line, format!(
+                    "unknown component '{}' in prefab '{}'", name, prefab.type_name
+                )));
+            }
+        }
+
+        let mut probe = T::default();
+        for (field, value, line) in &prefab.fields {
+            if let Err(message) = probe.set_field(field, value) {
+                return Err(PrefabError::new(file, *line, format!(
+                    "field '{}' of prefab '{}': {}", field, prefab.type_name, message
+                )));
+            }
+        }
+
+        let base = prefab.extends.as_ref().map(|e| e.0.as_str());
+        factories.push(FactoryEntry::new(&prefab.type_name, base, Box::new(PrefabFactory {
+            components: prefab.components.iter().map(|c| c.0.clone()).collect(),
+            fields: prefab.fields.iter().map(|f| (f.0.clone(), f.1.clone())).collect(),
+        })));
+    }
+    Ok(factories)
+}
+
+// points a factory resolve error back at the prefab section that caused it
+pub(crate) fn locate_error(file: &str, prefabs: &[Prefab], error: FactoryError) -> PrefabError {
+    let line = prefabs.iter()
+        .find(|p| Some(p.type_name.as_str()) == error.factory())
+        .map(|p| p.extends.as_ref().map_or(p.line, |e| e.1))
+        .unwrap_or(0);
+    PrefabError::new(file, line, error.to_string())
+}
+
+
+pub struct PrefabFactory {
+    components: Vec<String>,
+    fields: Vec<(String, String)>,
+}
+
+impl<'a, T: PrefabData> Factory<'a, T> for PrefabFactory {
+    fn make_spawn(&mut self, tools: &mut BuildTools<T>) {
+        for component in &self.components {
+            tools.add_component(component);
+        }
+        for (field, value) in &self.fields {
+            // values were validated when the prefab was loaded
+            let _ = tools.edit().set_field(field, value);
+        }
+    }
+
+    fn components(&self) -> Vec<NameTag> {
+        self.components.iter().map(|c| NameTag::from_str(c)).collect()
+    }
+}
@@ -0,0 +1,239 @@
+use std::fmt;
+use std::fmt::Write as _;
+use std::collections::VecDeque;
+use std::fs;
+use std::path::Path;
+use std::time::{ Duration, Instant };
+
+use super::SystemIndex;
+use super::json::quote;
+
+
+// Rolling statistics over the last `window` frames
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Timing {
+    pub last: Duration,
+    pub average: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SystemProfile {
+    pub system: SystemIndex,
+    pub label: String, // the components the system accesses
+    pub early_update: Timing,
+    pub update: Timing, // the on_update loop over all matching entities
+    pub requests: Timing,
+    pub matched: usize, // entities updated in the last frame
+    pub average_matched: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ProfileReport {
+    pub frames: u64, // frames recorded since the profiler was enabled
+    pub window: usize,
+    pub frame: Timing,
+    pub systems: Vec<SystemProfile>,
+}
+
+impl fmt::Display for ProfileReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} frames, statistics over the last {}", self.frames, self.window)?;
+        writeln!(f, "{:<28} {:>10} {:>10} {:>10} {:>10} {:>8}", "", "avg", "p50", "p95", "p99", "matched")?;
+        let row = |f: &mut fmt::Formatter, name: &str, t: &Timing, matched: String| writeln!(
+            f, "{:<28} {:>10?} {:>10?} {:>10?} {:>10?} {:>8}", name, t.average, t.p50, t.p95, t.p99, matched
+        );
+        row(f, "frame", &self.frame, String::new())?;
+        for s in &self.systems {
+            let name = format!("{} {}", s.system, s.label);
+            row(f, &name, &s.early_update, String::new())?;
+            row(f, "  on_update", &s.update, format!("{:.1}", s.average_matched))?;
+            row(f, "  requests", &s.requests, String::new())?;
+        }
+        Ok(())
+    }
+}
+
+
+struct Samples {
+    values: VecDeque<Duration>,
+}
+
+impl Samples {
+    fn new() -> Self {
+        Samples { values: VecDeque::new() }
+    }
+
+    fn push(&mut self, value: Duration, window: usize) {
+        if self.values.len() == window {
+            self.values.pop_front();
+        }
+        self.values.push_back(value);
+    }
+
+    fn timing(&self) -> Timing {
+        if self.values.is_empty() {
+            return Timing::default();
+        }
+        let mut sorted: Vec<Duration> = self.values.iter().copied().collect();
+        sorted.sort_unstable();
+        let at = |p: usize| sorted[((sorted.len() - 1) * p + 50) / 100];
+        Timing {
+            last: *self.values.back().unwrap(),
+            average: sorted.iter().sum::<Duration>() / sorted.len() as u32,
+            p50: at(50),
+            p95: at(95),
+            p99: at(99),
+            max: *sorted.last().unwrap(),
+        }
+    }
+}
+
+struct SystemSamples {
+    label: String,
+    early_update: Samples,
+    update: Samples,
+    requests: Samples,
+    matched: VecDeque<usize>,
+}
+
+// one complete phase of one system, kept for the trace export
+struct Span {
+    name: &'static str,
+    system: Option<SystemIndex>,
+    start: Duration, // since the profiler was created
+    duration: Duration,
+    matched: usize,
+}
+
+
+pub(crate) struct Profiler {
+    window: usize,
+    origin: Instant,
+    frames: u64,
+    frame_start: Option<Instant>,
+    frame: Samples,
+    systems: Vec<SystemSamples>,
+    spans: VecDeque<Vec<Span>>, // per frame, last `window` frames
+}
+
+impl Profiler {
+    pub(crate) fn new(window: usize, labels: Vec<String>) -> Self {
+        let window = window.max(1);
+        Profiler {
+            window,
+            origin: Instant::now(),
+            frames: 0,
+            frame_start: None,
+            frame: Samples::new(),
+            systems: labels.into_iter().map(|label| SystemSamples {
+                label,
+                early_update: Samples::new(),
+                update: Samples::new(),
+                requests: Samples::new(),
+                matched: VecDeque::new(),
+            }).collect(),
+            spans: VecDeque::new(),
+        }
+    }
+
+    pub(crate) fn begin_frame(&mut self) {
+        if self.spans.len() == self.window {
+            self.spans.pop_front();
+        }
+        self.spans.push_back(Vec::new());
+        self.frame_start = Some(Instant::now());
+    }
+
+    pub(crate) fn end_frame(&mut self) {
+        if let Some(start) = self.frame_start.take() {
+            let duration = start.elapsed();
+            self.frame.push(duration, self.window);
+            self.record("frame", None, start, duration, 0);
+            self.frames += 1;
+        }
+    }
+
+    // started: when early update began, updated: when the on_update loop began
+    pub(crate) fn record_system(&mut self, system: SystemIndex, started: Instant, updated: Instant, matched: usize) {
+        let now = Instant::now();
+        let window = self.window;
+        let samples = &mut self.systems[system];
+        samples.early_update.push(updated - started, window);
+        samples.update.push(now - updated, window);
+        if samples.matched.len() == window {
+            samples.matched.pop_front();
+        }
+        samples.matched.push_back(matched);
+
+        self.record("on_early_update", Some(system), started, updated - started, 0);
+        self.record("on_update", Some(system), updated, now - updated, matched);
+    }
+
+    pub(crate) fn record_requests(&mut self, system: SystemIndex, started: Instant) {
+        let duration = started.elapsed();
+        self.systems[system].requests.push(duration, self.window);
+        self.record("requests", Some(system), started, duration, 0);
+    }
+
+    fn record(&mut self, name: &'static str, system: Option<SystemIndex>, start: Instant, duration: Duration, matched: usize) {
+        if let Some(frame) = self.spans.back_mut() {
+            frame.push(Span { name, system, start: start - self.origin, duration, matched });
+        }
+    }
+
+    pub(crate) fn report(&self) -> ProfileReport {
+        ProfileReport {
+            frames: self.frames,
+            window: self.window,
+            frame: self.frame.timing(),
+            systems: self.systems.iter().enumerate().map(|(i, s)| SystemProfile {
+                system: i,
+                label: s.label.clone(),
+                early_update: s.early_update.timing(),
+                update: s.update.timing(),
+                requests: s.requests.timing(),
+                matched: s.matched.back().copied().unwrap_or(0),
+                average_matched: match s.matched.len() {
+                    0 => 0.0,
+                    n => s.matched.iter().sum::<usize>() as f64 / n as f64,
+                },
+            }).collect(),
+        }
+    }
+
+    // Chrome trace event format, open it in chrome://tracing or Perfetto. Frames are on
+    // the first track, every system gets a track of its own.
+    pub(crate) fn chrome_trace(&self) -> String {
+        let mut json = String::from("{\"traceEvents\":[");
+        let mut first = true;
+
+        for (i, s) in self.systems.iter().enumerate() {
+            let _ = write!(json, "{}{{\"name\":\"thread_name\",\"ph\":\"M\",\"pid\":1,\"tid\":{},\"args\":{{\"name\":{}}}}}",
+                if first { "" } else { "," }, i + 1, quote(&format!("system {} {}", i, s.label)));
+            first = false;
+        }
+        for span in self.spans.iter().flatten() {
+            let (tid, name) = match span.system {
+                Some(system) => (system + 1, format!("{} {}", span.name, system)),
+                None => (0, span.name.to_string()),
+            };
+            let _ = write!(json, "{}{{\"name\":{},\"cat\":\"ecs\",\"ph\":\"X\",\"pid\":1,\"tid\":{},\"ts\":{:.3},\"dur\":{:.3},\"args\":{{\"matched\":{}}}}}",
+                if first { "" } else { "," },
+                quote(&name), tid,
+                span.start.as_secs_f64() * 1e6,
+                span.duration.as_secs_f64() * 1e6,
+                span.matched);
+            first = false;
+        }
+        json.push_str("]}");
+        json
+    }
+
+    pub(crate) fn write_chrome_trace<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        fs::write(path, self.chrome_trace())
+    }
+}
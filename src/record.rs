@@ -0,0 +1,347 @@
+use std::fmt;
+use std::fmt::Write as _;
+use std::fmt::Debug;
+use std::fs;
+use std::path::Path;
+
+use super::json::quote;
+use super::{ Ecs, ObjectIndex, Reflect, ReflectError, Value, SceneId, SceneError };
+
+const HEADER: &str = "# simple_ecs recording 1";
+
+
+// External mutations of a world, run through Ecs::execute so they can be recorded
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Start,
+    // an update with its frame time, see Ecs::update_dt
+    Update(f32),
+    Spawn { name: String, factory: String },
+    // spawn args can only be recorded as strings, like the args of a scene file
+    SpawnWith { name: String, factory: String, args: Vec<(String, String)> },
+    SpawnBatch { factory: String, count: usize },
+    // the scene source is kept in the recording, so replays don't depend on the file
+    SpawnScene { file: String, source: String },
+    UnloadScene(SceneId),
+    Destroy(ObjectIndex),
+    Rename(ObjectIndex, String),
+    AddComponent(ObjectIndex, String),
+    RemoveComponent(ObjectIndex, String),
+    // writes a field of the object by Reflect path
+    Set { target: ObjectIndex, path: String, value: Value },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CommandError {
+    NotAlive(ObjectIndex),
+    Field(ReflectError),
+    Scene(SceneError),
+}
+
+impl fmt::Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CommandError::NotAlive(target) => write!(f, "object {} is not alive", target),
+            CommandError::Field(e) => write!(f, "{}", e),
+            CommandError::Scene(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayError {
+    Read(String),
+    Parse { line: usize, message: String },
+    // frame 0 is the world before the first command
+    Diverged { frame: usize, expected: u64, found: u64 },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Read(message) => write!(f, "could not read recording: {}", message),
+            ReplayError::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            ReplayError::Diverged { frame, expected, found } =>
+                write!(f, "frame {} diverged, checksum {:016x} instead of {:016x}", frame, found, expected),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+
+// Commands in the order they were executed, with the world checksum after every
+// frame. Saved as text, one command per line:
+//
+//   # simple_ecs recording 1
+//   begin 8c2f0a1b3d4e5f60
+//   spawn "player" "walker"
+//   spawn_with "orc" "walker" "team" "red"
+//   set 3 "position.x" float 2.5
+//   update 0.016 1a2b3c4d5e6f7081
+//
+// The world keeps no resources besides its objects, so there are none to record.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Recording {
+    pub initial: u64,
+    pub entries: Vec<(Command, Option<u64>)>, // frames carry the checksum after them
+}
+
+impl Recording {
+    pub fn frames(&self) -> usize {
+        self.entries.iter().filter(|e| e.1.is_some()).count()
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ReplayError> {
+        let source = fs::read_to_string(path.as_ref())
+            .map_err(|e| ReplayError::Read(format!("{}: {}", path.as_ref().display(), e)))?;
+        Self::parse(&source)
+    }
+
+    pub fn parse(source: &str) -> Result<Self, ReplayError> {
+        let mut initial = None;
+        let mut entries = Vec::new();
+
+        for (i, raw) in source.lines().enumerate() {
+            let line = i + 1;
+            let error = |message: String| ReplayError::Parse { line, message };
+            let text = raw.trim();
+            if text.is_empty() || text.starts_with('#') {
+                continue;
+            }
+            let words = split(text).map_err(error)?;
+            let word = |i: usize| words.get(i).map(String::as_str).ok_or_else(|| error(format!("'{}' is missing arguments", words[0])));
+            let index = |i: usize| word(i)?.parse::<ObjectIndex>().map_err(|_| error("expected an object index".to_string()));
+            let checksum = |i: usize| u64::from_str_radix(word(i)?, 16).map_err(|_| error("expected a checksum".to_string()));
+
+            let entry = match words[0].as_str() {
+                "begin" => { initial = Some(checksum(1)?); continue; },
+                "start" => (Command::Start, Some(checksum(1)?)),
+                "update" => (Command::Update(word(1)?.parse().map_err(|_| error("expected a frame time".to_string()))?), Some(checksum(2)?)),
+                "spawn" => (Command::Spawn { name: word(1)?.to_string(), factory: word(2)?.to_string() }, None),
+                "spawn_with" => {
+                    if words.len() % 2 == 0 {
+                        return Err(error("spawn_with args come in key value pairs".to_string()));
+                    }
+                    let args = words[3..].chunks(2).map(|pair| (pair[0].clone(), pair[1].clone())).collect();
+                    (Command::SpawnWith { name: word(1)?.to_string(), factory: word(2)?.to_string(), args }, None)
+                },
+                "scene" => (Command::SpawnScene { file: word(1)?.to_string(), source: word(2)?.to_string() }, None),
+                "unload" => (Command::UnloadScene(SceneId(word(1)?.parse().map_err(|_| error("expected a scene id".to_string()))?)), None),
+                "spawn_batch" => (Command::SpawnBatch {
+                    factory: word(1)?.to_string(),
+                    count: word(2)?.parse().map_err(|_| error("expected a count".to_string()))?,
+                }, None),
+                "destroy" => (Command::Destroy(index(1)?), None),
+                "rename" => (Command::Rename(index(1)?, word(2)?.to_string()), None),
+                "add" => (Command::AddComponent(index(1)?, word(2)?.to_string()), None),
+                "remove" => (Command::RemoveComponent(index(1)?, word(2)?.to_string()), None),
+                "set" => {
+                    let value = match (word(3)?, word(4)?) {
+                        ("bool", v) => v.parse().map(Value::Bool).map_err(|_| error("expected a bool".to_string()))?,
+                        ("int", v) => v.parse().map(Value::Int).map_err(|_| error("expected an int".to_string()))?,
+                        ("float", v) => v.parse().map(Value::Float).map_err(|_| error("expected a float".to_string()))?,
+                        ("str", v) => Value::Str(v.to_string()),
+                        (other, _) => return Err(error(format!("unknown value type '{}'", other))),
+                    };
+                    (Command::Set { target: index(1)?, path: word(2)?.to_string(), value }, None)
+                },
+                other => return Err(error(format!("unknown command '{}'", other))),
+            };
+            entries.push(entry);
+        }
+        match initial {
+            Some(initial) => Ok(Recording { initial, entries }),
+            None => Err(ReplayError::Parse { line: 0, message: "the recording has no begin line".to_string() }),
+        }
+    }
+
+    // runs the commands against a fresh world built like the recorded one
+    pub fn replay<T: Default + Debug + Reflect>(&self, ecs: &mut Ecs<T>) -> usize {
+        for (command, _) in &self.entries {
+            // failing commands failed while recording as well
+            let _ = ecs.execute(command);
+        }
+        self.frames()
+    }
+
+    // replays and stops at the first frame whose checksum differs from the recorded one,
+    // returns the number of frames verified
+    pub fn verify<T: Default + Debug + Reflect>(&self, ecs: &mut Ecs<T>) -> Result<usize, ReplayError> {
+        let found = ecs.checksum();
+        if found != self.initial {
+            return Err(ReplayError::Diverged { frame: 0, expected: self.initial, found });
+        }
+        let mut frame = 0;
+        for (command, checksum) in &self.entries {
+            let _ = ecs.execute(command);
+            if let Some(expected) = *checksum {
+                frame += 1;
+                let found = ecs.checksum();
+                if found != expected {
+                    return Err(ReplayError::Diverged { frame, expected, found });
+                }
+            }
+        }
+        Ok(frame)
+    }
+}
+
+impl fmt::Display for Recording {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", HEADER)?;
+        writeln!(f, "begin {:016x}", self.initial)?;
+        for (command, checksum) in &self.entries {
+            let mut line = String::new();
+            let _ = match command {
+                Command::Start => write!(line, "start"),
+                // Debug prints the shortest text that reads back as the same float
+                Command::Update(dt) => write!(line, "update {:?}", dt),
+                Command::Spawn { name, factory } => write!(line, "spawn {} {}", quote(name), quote(factory)),
+                Command::SpawnWith { name, factory, args } => {
+                    let _ = write!(line, "spawn_with {} {}", quote(name), quote(factory));
+                    args.iter().try_for_each(|(key, value)| write!(line, " {} {}", quote(key), quote(value)))
+                },
+                Command::SpawnScene { file, source } => write!(line, "scene {} {}", quote(file), quote(source)),
+                Command::UnloadScene(scene) => write!(line, "unload {}", scene.0),
+                Command::SpawnBatch { factory, count } => write!(line, "spawn_batch {} {}", quote(factory), count),
+                Command::Destroy(target) => write!(line, "destroy {}", target),
+                Command::Rename(target, name) => write!(line, "rename {} {}", target, quote(name)),
+                Command::AddComponent(target, component) => write!(line, "add {} {}", target, quote(component)),
+                Command::RemoveComponent(target, component) => write!(line, "remove {} {}", target, quote(component)),
+                Command::Set { target, path, value } => {
+                    let value = match value {
+                        Value::Bool(b) => format!("bool {}", b),
+                        Value::Int(i) => format!("int {}", i),
+                        // Debug prints the shortest text that reads back as the same float
+                        Value::Float(x) => format!("float {:?}", x),
+                        Value::Str(s) => format!("str {}", quote(s)),
+                    };
+                    write!(line, "set {} {} {}", target, quote(path), value)
+                },
+            };
+            if let Some(checksum) = checksum {
+                let _ = write!(line, " {:016x}", checksum);
+            }
+            writeln!(f, "{}", line)?;
+        }
+        Ok(())
+    }
+}
+
+
+// Executes commands and keeps them with the checksum of every frame:
+//
+//   let mut recorder = Recorder::new(&ecs);
+//   recorder.execute(&mut ecs, Command::Start)?;
+//   loop {
+//       recorder.execute(&mut ecs, Command::Update(dt))?;
+//   }
+//   recorder.recording().save("session.rec")?;
+//
+pub struct Recorder {
+    recording: Recording,
+}
+
+impl Recorder {
+    pub fn new<T: Default + Debug>(ecs: &Ecs<T>) -> Self {
+        Recorder { recording: Recording { initial: ecs.checksum(), entries: Vec::new() } }
+    }
+
+    // failing commands are recorded too, they fail the same way on replay
+    pub fn execute<T: Default + Debug + Reflect>(&mut self, ecs: &mut Ecs<T>, command: Command) -> Result<Vec<ObjectIndex>, CommandError> {
+        let result = ecs.execute(&command);
+        let checksum = match command {
+            Command::Start | Command::Update(_) => Some(ecs.checksum()),
+            _ => None,
+        };
+        self.recording.entries.push((command, checksum));
+        result
+    }
+
+    pub fn recording(&self) -> &Recording {
+        &self.recording
+    }
+
+    pub fn into_recording(self) -> Recording {
+        self.recording
+    }
+}
+
+
+// FNV-1a, stable across runs and platforms unlike the std hashers
+pub(crate) struct Checksum(pub(crate) u64);
+
+impl Checksum {
+    pub(crate) fn new() -> Self {
+        Checksum(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for b in bytes {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    pub(crate) fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+}
+
+impl fmt::Write for Checksum {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        self.write(s.as_bytes());
+        Ok(())
+    }
+}
+
+// words separated by spaces, or quoted
+fn split(text: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            ' ' | '\t' => continue,
+            '"' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some('n') => word.push('\n'),
+                            Some('r') => word.push('\r'),
+                            Some('t') => word.push('\t'),
+                            Some('u') => {
+                                let code: String = chars.by_ref().take(4).collect();
+                                let c = u32::from_str_radix(&code, 16).ok().and_then(char::from_u32)
+                                    .ok_or_else(|| format!("bad escape \\u{}", code))?;
+                                word.push(c);
+                            },
+                            Some(c) => word.push(c),
+                            None => return Err("unterminated string".to_string()),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err("unterminated string".to_string()),
+                    }
+                }
+                words.push(word);
+            },
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.peek().copied().filter(|c| *c != ' ' && *c != '\t') {
+                    word.push(c);
+                    chars.next();
+                }
+                words.push(word);
+            },
+        }
+    }
+    Ok(words)
+}
@@ -0,0 +1,254 @@
+use std::fmt;
+use std::convert::TryFrom;
+
+
+// Loosely typed value used to read and write object data by field name
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+}
+
+impl Value {
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Bool(_) => "bool",
+            Value::Int(_) => "int",
+            Value::Float(_) => "float",
+            Value::Str(_) => "string",
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Bool(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_int(&self) -> Option<i64> {
+        match self {
+            Value::Int(i) => Some(*i),
+            _ => None,
+        }
+    }
+
+    // ints are widened, so a float field accepts both
+    pub fn as_float(&self) -> Option<f64> {
+        match self {
+            Value::Int(i) => Some(*i as f64),
+            Value::Float(f) => Some(*f),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            Value::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Bool(b) => write!(f, "{}", b),
+            Value::Int(i) => write!(f, "{}", i),
+            Value::Float(x) => write!(f, "{}", x),
+            Value::Str(s) => write!(f, "{}", s),
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReflectError {
+    UnknownField(String),
+    TypeMismatch { path: String, expected: &'static str, found: &'static str },
+    OutOfRange { path: String, expected: &'static str, value: String },
+}
+
+impl ReflectError {
+    pub fn mismatch(path: &str, expected: &'static str, found: &Value) -> Self {
+        ReflectError::TypeMismatch { path: path.to_string(), expected, found: found.type_name() }
+    }
+
+    // prefixes the path of the field the error was found on with the field containing it
+    pub fn within(self, field: &str) -> Self {
+        let join = |path: String| if path.is_empty() { field.to_string() } else { format!("{}.{}", field, path) };
+        match self {
+            ReflectError::UnknownField(path) => ReflectError::UnknownField(join(path)),
+            ReflectError::TypeMismatch { path, expected, found } =>
+                ReflectError::TypeMismatch { path: join(path), expected, found },
+            ReflectError::OutOfRange { path, expected, value } =>
+                ReflectError::OutOfRange { path: join(path), expected, value },
+        }
+    }
+}
+
+impl fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReflectError::UnknownField(path) => write!(f, "unknown field '{}'", path),
+            ReflectError::TypeMismatch { path, expected, found } =>
+                write!(f, "field '{}' expects {}, found {}", path, expected, found),
+            ReflectError::OutOfRange { path, expected, value } =>
+                write!(f, "value {} does not fit field '{}' of type {}", value, path, expected),
+        }
+    }
+}
+
+impl std::error::Error for ReflectError {}
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    pub type_name: &'static str,
+}
+
+
+// Access to object data by field name for code that doesn't know T, such as
+// scripts, editors and serialization. Structs implement the field methods, usually
+// through #[derive(Reflect)], while primitives are leaves that hold a Value:
+//
+//   #[derive(Default, Debug, Reflect)]
+//   struct Cell {
+//       position: Position,
+//       health: u32,
+//       #[reflect(skip)]
+//       cache: Vec<u8>,
+//   }
+//
+//   cell.set_path("position.x", Value::Float(2.0))?;
+//
+// Paths are field names joined by dots, tuple struct fields are named 0, 1, ...
+pub trait Reflect {
+    fn type_name(&self) -> &'static str {
+        std::any::type_name::<Self>()
+    }
+
+    fn fields(&self) -> Vec<FieldInfo> { Vec::new() }
+
+    #[allow(unused_variables)]
+    fn field(&self, name: &str) -> Option<&dyn Reflect> { None }
+
+    #[allow(unused_variables)]
+    fn field_mut(&mut self, name: &str) -> Option<&mut dyn Reflect> { None }
+
+    // only leaves have a value
+    fn value(&self) -> Option<Value> { None }
+
+    fn set_value(&mut self, value: Value) -> Result<(), ReflectError> {
+        Err(ReflectError::mismatch("", "a struct", &value))
+    }
+
+    fn get_path(&self, path: &str) -> Option<Value> {
+        if path.is_empty() {
+            return self.value();
+        }
+        match path.split_once('.') {
+            Some((name, rest)) => self.field(name)?.get_path(rest),
+            None => self.field(path)?.value(),
+        }
+    }
+
+    fn set_path(&mut self, path: &str, value: Value) -> Result<(), ReflectError> {
+        if path.is_empty() {
+            return self.set_value(value);
+        }
+        let (name, rest) = path.split_once('.').unwrap_or((path, ""));
+        match self.field_mut(name) {
+            Some(field) => field.set_path(rest, value).map_err(|e| e.within(name)),
+            None => Err(ReflectError::UnknownField(path.to_string())),
+        }
+    }
+
+    // every leaf below this one with its full path
+    fn flatten(&self) -> Vec<(String, Value)> {
+        let mut leaves = Vec::new();
+        if let Some(value) = self.value() {
+            leaves.push((String::new(), value));
+        }
+        for info in self.fields() {
+            if let Some(field) = self.field(info.name) {
+                for (path, value) in field.flatten() {
+                    let path = if path.is_empty() { info.name.to_string() } else { format!("{}.{}", info.name, path) };
+                    leaves.push((path, value));
+                }
+            }
+        }
+        leaves
+    }
+}
+
+
+macro_rules! reflect_int {
+    ($($int:ty),*) => { $(
+        impl Reflect for $int {
+            fn value(&self) -> Option<Value> {
+                // values beyond i64 are only readable as a float
+                Some(i64::try_from(*self).map_or(Value::Float(*self as f64), Value::Int))
+            }
+
+            fn set_value(&mut self, value: Value) -> Result<(), ReflectError> {
+                let int = value.as_int().ok_or_else(|| ReflectError::mismatch("", stringify!($int), &value))?;
+                *self = <$int>::try_from(int).map_err(|_| ReflectError::OutOfRange {
+                    path: String::new(),
+                    expected: stringify!($int),
+                    value: int.to_string(),
+                })?;
+                Ok(())
+            }
+        }
+    )* };
+}
+
+reflect_int!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+macro_rules! reflect_float {
+    ($($float:ty),*) => { $(
+        impl Reflect for $float {
+            fn value(&self) -> Option<Value> {
+                Some(Value::Float(*self as f64))
+            }
+
+            fn set_value(&mut self, value: Value) -> Result<(), ReflectError> {
+                *self = value.as_float().ok_or_else(|| ReflectError::mismatch("", stringify!($float), &value))? as $float;
+                Ok(())
+            }
+        }
+    )* };
+}
+
+reflect_float!(f32, f64);
+
+impl Reflect for bool {
+    fn value(&self) -> Option<Value> {
+        Some(Value::Bool(*self))
+    }
+
+    fn set_value(&mut self, value: Value) -> Result<(), ReflectError> {
+        *self = value.as_bool().ok_or_else(|| ReflectError::mismatch("", "bool", &value))?;
+        Ok(())
+    }
+}
+
+impl Reflect for String {
+    fn type_name(&self) -> &'static str { "String" }
+
+    fn value(&self) -> Option<Value> {
+        Some(Value::Str(self.clone()))
+    }
+
+    fn set_value(&mut self, value: Value) -> Result<(), ReflectError> {
+        match value {
+            Value::Str(s) => *self = s,
+            other => return Err(ReflectError::mismatch("", "string", &other)),
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,491 @@
+use std::fmt;
+use std::cell::RefCell;
+use std::collections::{ HashMap, VecDeque };
+use std::rc::Rc;
+
+use super::*;
+
+
+// Moves encoded packets between a server and one client, both ends get their own
+pub trait Transport {
+    fn send(&mut self, packet: Vec<u8>);
+    fn receive(&mut self) -> Option<Vec<u8>>;
+}
+
+// In-process transport, packets sent on one end are received on the other
+pub struct Loopback {
+    outgoing: Rc<RefCell<VecDeque<Vec<u8>>>>,
+    incoming: Rc<RefCell<VecDeque<Vec<u8>>>>,
+}
+
+impl Loopback {
+    pub fn pair() -> (Loopback, Loopback) {
+        let a = Rc::new(RefCell::new(VecDeque::new()));
+        let b = Rc::new(RefCell::new(VecDeque::new()));
+        (
+            Loopback { outgoing: a.clone(), incoming: b.clone() },
+            Loopback { outgoing: b, incoming: a },
+        )
+    }
+
+    pub fn pending(&self) -> usize {
+        self.incoming.borrow().len()
+    }
+}
+
+impl Transport for Loopback {
+    fn send(&mut self, packet: Vec<u8>) {
+        self.outgoing.borrow_mut().push_back(packet);
+    }
+
+    fn receive(&mut self) -> Option<Vec<u8>> {
+        self.incoming.borrow_mut().pop_front()
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplicationError {
+    Decode(String),
+    UnknownEntity(u64),
+    SpawnFailed(String),
+    NameTaken { entity: u64, name: String },
+    TooManyComponents,
+    Field(ReflectError),
+}
+
+impl fmt::Display for ReplicationError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplicationError::Decode(e) => write!(f, "malformed replication packet: {}", e),
+            ReplicationError::UnknownEntity(id) => write!(f, "update for unknown server entity {}", id),
+            ReplicationError::SpawnFailed(name) => write!(f, "could not spawn replicated entity '{}'", name),
+            ReplicationError::NameTaken { entity, name } => 
+                write!(f, "name '{}' of server entity {} is taken, it was mirrored without a name", name, entity),
+            ReplicationError::TooManyComponents => 
+                write!(f, "at most {} components can be replicated", MAX_COMPONENTS),
+            ReplicationError::Field(e) => write!(f, "could not apply replicated field: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ReplicationError {}
+
+
+// Which components are mirrored and which object fields belong to them. Server and
+// clients have to be set up with the same components in the same order:
+//
+//   let replication = Replication::new()
+//       .component("position", &["x", "y"])?
+//       .component("health", &["health"])?;
+//
+// An entity is replicated while it has at least one of the components, and only the
+// fields of the components it has are sent. At most 32 components can be replicated.
+#[derive(Debug, Clone, Default)]
+pub struct Replication {
+    components: Vec<(String, Vec<usize>)>, // component name and the ids of its fields
+    fields: Vec<String>, // field paths, their position is the id used on the wire
+}
+
+impl Replication {
+    pub fn new() -> Self {
+        Replication::default()
+    }
+
+    pub fn component(mut self, name: &str, fields: &[&str]) -> Result<Self, ReplicationError> {
+        if self.components.len() == MAX_COMPONENTS {
+            return Err(ReplicationError::TooManyComponents);
+        }
+        let mut ids = Vec::with_capacity(fields.len());
+        for field in fields {
+            let id = match self.fields.iter().position(|f| f == field) {
+                Some(id) => id,
+                None => {
+                    self.fields.push(field.to_string());
+                    self.fields.len() - 1
+                },
+            };
+            ids.push(id);
+        }
+        self.components.push((name.to_string(), ids));
+        Ok(self)
+    }
+
+    // replicated components of an entity as a mask over the component list above
+    fn mask(&self, entity: &BitFlags, refs: &ComponentRefs) -> u32 {
+        let mut mask = 0;
+        for (bit, (name, _)) in self.components.iter().enumerate() {
+            if let Some(c) = refs.get(&NameTag::from_str(name)) {
+                if entity.bit(*c.index()) {
+                    mask |= 1 << bit;
+                }
+            }
+        }
+        mask
+    }
+
+    fn fields_of(&self, mask: u32) -> Vec<usize> {
+        let mut ids: Vec<usize> = Vec::new();
+        for (bit, (_, fields)) in self.components.iter().enumerate() {
+            if mask & (1 << bit) != 0 {
+                for field in fields {
+                    if !ids.contains(field) {
+                        ids.push(*field);
+                    }
+                }
+            }
+        }
+        ids
+    }
+}
+
+
+// components are sent as a u32 mask
+const MAX_COMPONENTS: usize = 32;
+
+pub type ClientId = usize;
+
+// what a client was last sent about an entity
+struct Known {
+    generation: u32,
+    mask: u32,
+    values: Vec<Option<Value>>, // by field id
+}
+
+struct Client {
+    transport: Box<dyn Transport>,
+    known: HashMap<ObjectIndex, Known>,
+    synced: u64, // tick of the last replicate
+}
+
+// Server side: sends every client what changed since it was last replicated to
+pub struct ReplicationServer {
+    replication: Replication,
+    clients: Vec<Option<Client>>,
+}
+
+impl ReplicationServer {
+    pub fn new(replication: Replication) -> Self {
+        ReplicationServer { replication, clients: Vec::new() }
+    }
+
+    // a new client is sent the full state on the next replicate
+    pub fn add_client(&mut self, transport: Box<dyn Transport>) -> ClientId {
+        self.clients.push(Some(Client { transport, known: HashMap::new(), synced: 0 }));
+        self.clients.len() - 1
+    }
+
+    pub fn remove_client(&mut self, client: ClientId) -> Option<Box<dyn Transport>> {
+        self.clients.get_mut(client).and_then(|c| c.take()).map(|c| c.transport)
+    }
+
+    // only objects changed since the last replicate are compared field by field
+    pub fn replicate<T: Default + Debug + Reflect>(&mut self, ecs: &Ecs<T>) {
+        for client in self.clients.iter_mut().flatten() {
+            let packet = delta(&self.replication, client, ecs);
+            client.synced = ecs.tick();
+            if !packet.is_empty() {
+                client.transport.send(packet);
+            }
+        }
+    }
+}
+
+const SPAWN: u8 = 1;
+const UPDATE: u8 = 2;
+const DESPAWN: u8 = 3;
+
+fn delta<T: Default + Debug + Reflect>(replication: &Replication, client: &mut Client, ecs: &Ecs<T>) -> Vec<u8> {
+    let entities = &ecs.entities;
+    let mut packet = Writer::default();
+
+    // despawns first, the slot might be spawned into again further down
+    let mut gone: Vec<ObjectIndex> = client.known.iter()
+        .filter(|(id, known)| {
+            !entities.is_alive(id)
+                || entities.generations[**id] != known.generation
+                || replication.mask(&entities.pool[**id], &ecs.component_refs) == 0
+        })
+        .map(|(id, _)| *id)
+        .collect();
+    gone.sort_unstable();
+    for id in gone {
+        client.known.remove(&id);
+        packet.byte(DESPAWN);
+        packet.varint(id as u64);
+    }
+
+    for id in ecs.changed_since(client.synced) {
+        let mask = replication.mask(&entities.pool[id], &ecs.component_refs);
+        if mask == 0 {
+            continue;
+        }
+        let object = &ecs.objects.pool[id];
+        let known = client.known.entry(id).or_insert_with(|| Known {
+            generation: 0,
+            mask: 0,
+            values: vec![None; replication.fields.len()],
+        });
+        let spawned = known.generation != entities.generations[id];
+
+        let mut changed = Vec::new();
+        for field in replication.fields_of(mask) {
+            let value = object.get_path(&replication.fields[field]);
+            if spawned || value != known.values[field] {
+                if let Some(value) = &value {
+                    changed.push((field, value.clone()));
+                }
+                known.values[field] = value;
+            }
+        }
+        if !spawned && changed.is_empty() && mask == known.mask {
+            continue;
+        }
+
+        if spawned {
+            packet.byte(SPAWN);
+            packet.varint(id as u64);
+            let name = ecs.objects.symbols.resolve(ecs.objects.active[entities.sparse[id]].1);
+            packet.string(name);
+        } else {
+            packet.byte(UPDATE);
+            packet.varint(id as u64);
+        }
+        packet.varint(mask as u64);
+        packet.varint(changed.len() as u64);
+        for (field, value) in changed {
+            packet.varint(field as u64);
+            packet.value(&value);
+        }
+        known.generation = entities.generations[id];
+        known.mask = mask;
+    }
+    packet.0
+}
+
+
+// Client side: mirrors the server entities into a local Ecs
+pub struct ReplicationClient {
+    replication: Replication,
+    transport: Box<dyn Transport>,
+    entities: HashMap<u64, ObjectIndex>, // server handle -> local handle
+}
+
+impl ReplicationClient {
+    pub fn new(replication: Replication, transport: Box<dyn Transport>) -> Self {
+        ReplicationClient { replication, transport, entities: HashMap::new() }
+    }
+
+    // local handle of an entity known by its server handle
+    pub fn local(&self, server: ObjectIndex) -> Option<ObjectIndex> {
+        self.entities.get(&(server as u64)).copied()
+    }
+
+    // applies every packet received so far, returns how many there were. A packet is decoded
+    // completely before it touches the local Ecs, so a malformed one changes nothing. A field
+    // that can not be set stops its packet, the messages before it stay applied. An entity
+    // whose name is taken in the local Ecs is mirrored without a name, the NameTaken error is
+    // returned after the rest of its packet was applied.
+    pub fn apply<T: Default + Debug + Reflect>(&mut self, ecs: &mut Ecs<T>) -> Result<usize, ReplicationError> {
+        let mut applied = 0;
+        while let Some(packet) = self.transport.receive() {
+            let messages = self.decode(&packet)?;
+            self.apply_messages(messages, ecs)?;
+            applied += 1;
+        }
+        Ok(applied)
+    }
+
+    fn decode(&self, packet: &[u8]) -> Result<Vec<Message>, ReplicationError> {
+        let mut reader = Reader { bytes: packet, at: 0 };
+        let mut messages = Vec::new();
+        // server entities spawned (true) or despawned (false) earlier in this packet
+        let mut seen = HashMap::new();
+
+        while !reader.done() {
+            let kind = reader.byte()?;
+            let server = reader.varint()?;
+
+            let name = match kind {
+                DESPAWN => {
+                    seen.insert(server, false);
+                    messages.push(Message::Despawn(server));
+                    continue;
+                },
+                SPAWN => {
+                    seen.insert(server, true);
+                    Some(reader.string()?)
+                },
+                UPDATE => {
+                    let known = seen.get(&server).copied().unwrap_or_else(|| self.entities.contains_key(&server));
+                    if !known {
+                        return Err(ReplicationError::UnknownEntity(server));
+                    }
+                    None
+                },
+                other => return Err(ReplicationError::Decode(format!("unknown message kind {}", other))),
+            };
+
+            let mask = reader.varint()?;
+            let count = reader.varint()?;
+            let mut fields = Vec::new();
+            for _ in 0..count {
+                let field = reader.varint()? as usize;
+                if field >= self.replication.fields.len() {
+                    return Err(ReplicationError::Decode(format!("unknown field id {}", field)));
+                }
+                fields.push((field, reader.value()?));
+            }
+            messages.push(Message::Set { server, name, mask, fields });
+        }
+        Ok(messages)
+    }
+
+    fn apply_messages<T: Default + Debug + Reflect>(&mut self, messages: Vec<Message>, ecs: &mut Ecs<T>) -> Result<(), ReplicationError> {
+        let mut result = Ok(());
+
+        for message in messages {
+            let (server, name, mask, fields) = match message {
+                Message::Despawn(server) => {
+                    if let Some(local) = self.entities.remove(&server) {
+                        ecs.destroy(&local);
+                    }
+                    continue;
+                },
+                Message::Set { server, name, mask, fields } => (server, name, mask, fields),
+            };
+
+            let local = match name {
+                Some(name) => {
+                    // the previous entity in a reused server slot may hold the name
+                    if let Some(previous) = self.entities.remove(&server) {
+                        ecs.destroy(&previous);
+                    }
+                    let named = ecs.objects.name_available(&name);
+                    let local = ecs.spawn(if named { &name } else { "" }, "")
+                        .ok_or_else(|| ReplicationError::SpawnFailed(name.clone()))?;
+                    if !named && result.is_ok() {
+                        result = Err(ReplicationError::NameTaken { entity: server, name });
+                    }
+                    self.entities.insert(server, local);
+                    local
+                },
+                None => *self.entities.get(&server).ok_or(ReplicationError::UnknownEntity(server))?,
+            };
+
+            for (bit, (name, _)) in self.replication.components.iter().enumerate() {
+                if mask & (1 << bit) != 0 {
+                    ecs.add_component(&local, name);
+                } else {
+                    ecs.remove_component(&local, name);
+                }
+            }
+
+            for (field, value) in fields {
+                let path = &self.replication.fields[field];
+                ecs.get_mut(&local).set_path(path, value).map_err(ReplicationError::Field)?;
+            }
+        }
+        result
+    }
+}
+
+
+// A decoded message, spawns carry the name of the entity
+enum Message {
+    Despawn(u64),
+    Set { server: u64, name: Option<String>, mask: u64, fields: Vec<(usize, Value)> },
+}
+
+
+// Packets are a list of messages: a kind byte, the server handle, for spawns the name,
+// then the component mask and the changed fields. Numbers are LEB128 varints.
+#[derive(Default)]
+struct Writer(Vec<u8>);
+
+impl Writer {
+    fn byte(&mut self, byte: u8) {
+        self.0.push(byte);
+    }
+
+    fn varint(&mut self, mut n: u64) {
+        while n >= 0x80 {
+            self.0.push((n as u8) | 0x80);
+            n >>= 7;
+        }
+        self.0.push(n as u8);
+    }
+
+    fn string(&mut self, s: &str) {
+        self.varint(s.len() as u64);
+        self.0.extend_from_slice(s.as_bytes());
+    }
+
+    fn value(&mut self, value: &Value) {
+        match value {
+            Value::Bool(b) => { self.byte(0); self.byte(*b as u8) },
+            // zigzag, so small negative numbers stay small
+            Value::Int(i) => { self.byte(1); self.varint(((i << 1) ^ (i >> 63)) as u64) },
+            Value::Float(x) => { self.byte(2); self.0.extend_from_slice(&x.to_le_bytes()) },
+            Value::Str(s) => { self.byte(3); self.string(s) },
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn done(&self) -> bool {
+        self.at >= self.bytes.len()
+    }
+
+    fn take(&mut self, count: usize) -> Result<&'a [u8], ReplicationError> {
+        if count > self.bytes.len() - self.at {
+            return Err(ReplicationError::Decode("packet ends early".to_string()));
+        }
+        self.at += count;
+        Ok(&self.bytes[self.at - count..self.at])
+    }
+
+    fn byte(&mut self) -> Result<u8, ReplicationError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn varint(&mut self) -> Result<u64, ReplicationError> {
+        let mut n = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.byte()?;
+            n |= ((byte & 0x7f) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(n);
+            }
+        }
+        Err(ReplicationError::Decode("varint too long".to_string()))
+    }
+
+    fn string(&mut self) -> Result<String, ReplicationError> {
+        let len = self.varint()? as usize;
+        let bytes = self.take(len)?;
+        String::from_utf8(bytes.to_vec()).map_err(|e| ReplicationError::Decode(e.to_string()))
+    }
+
+    fn value(&mut self) -> Result<Value, ReplicationError> {
+        match self.byte()? {
+            0 => Ok(Value::Bool(self.byte()? != 0)),
+            1 => {
+                let n = self.varint()?;
+                Ok(Value::Int(((n >> 1) as i64) ^ -((n & 1) as i64)))
+            },
+            2 => {
+                let mut bytes = [0; 8];
+                bytes.copy_from_slice(self.take(8)?);
+                Ok(Value::Float(f64::from_le_bytes(bytes)))
+            },
+            3 => Ok(Value::Str(self.string()?)),
+            other => Err(ReplicationError::Decode(format!("unknown value type {}", other))),
+        }
+    }
+}
@@ -0,0 +1,90 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+
+// A group of objects that is loaded and unloaded as a whole, see Ecs::spawn_scene
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SceneId(pub(crate) u32);
+
+impl fmt::Display for SceneId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "scene {}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneError {
+    pub file: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl SceneError {
+    pub(crate) fn new(file: &str, line: usize, message: String) -> Self {
+        SceneError { file: file.to_string(), line, message }
+    }
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.file, self.line, self.message)
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+
+// One object per line, the factory to spawn it with, an optional name and arguments
+// that reach the factory as String spawn args:
+//
+//   # level-1.scene
+//   hero player
+//   orc grunt team=red
+//   orc
+//
+#[derive(Debug, Clone, PartialEq)]
+pub struct SceneEntry {
+    pub factory: String,
+    pub name: String,
+    pub args: Vec<(String, String)>,
+    pub line: usize,
+}
+
+pub fn parse_scene(file: &str, source: &str) -> Result<Vec<SceneEntry>, SceneError> {
+    let mut entries = Vec::new();
+
+    for (i, raw) in source.lines().enumerate() {
+        let line = i + 1;
+        let text = raw.trim();
+        if text.is_empty() || text.starts_with('#') {
+            continue;
+        }
+
+        let mut words = text.split_whitespace();
+        let factory = words.next().unwrap_or_default();
+        if factory.contains('=') {
+            return Err(SceneError::new(file, line, format!("expected a factory name, found '{}'", factory)));
+        }
+        let mut entry = SceneEntry { factory: factory.to_string(), name: String::new(), args: Vec::new(), line };
+
+        for word in words {
+            match word.find('=') {
+                Some(0) => return Err(SceneError::new(file, line, format!("missing key before '=' in '{}'", word))),
+                Some(at) => entry.args.push((word[..at].to_string(), word[at + 1..].to_string())),
+                None if entry.name.is_empty() && entry.args.is_empty() => entry.name = word.to_string(),
+                None => return Err(SceneError::new(file, line, format!("expected 'key=value', found '{}'", word))),
+            }
+        }
+        entries.push(entry);
+    }
+    Ok(entries)
+}
+
+pub(crate) fn read_scene<P: AsRef<Path>>(path: P) -> Result<(String, String), SceneError> {
+    let file = path.as_ref().display().to_string();
+    match fs::read_to_string(path.as_ref()) {
+        Ok(source) => Ok((file, source)),
+        Err(e) => Err(SceneError::new(&file, 0, format!("could not read file: {}", e))),
+    }
+}
@@ -0,0 +1,731 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use super::*;
+use super::reflect::*;
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptError {
+    pub script: String,
+    pub line: usize,
+    pub message: String,
+}
+
+impl ScriptError {
+    fn new(script: &str, line: usize, message: String) -> Self {
+        ScriptError { script: script.to_string(), line, message }
+    }
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.script, self.line, self.message)
+    }
+}
+
+impl std::error::Error for ScriptError {}
+
+
+// A behaviour written in a small interpreted language, objects are read and written
+// through Reflect, any name not declared with `let` is a field of the target object:
+//
+//   components "walker", position
+//   without frozen
+//   changed position
+//
+//   on_startup {
+//       spawn("guard", "walker")
+//   }
+//
+//   on_update {
+//       let step = speed * 0.5
+//       x += step
+//       if x > 100 and not respawned {
+//           spawn("", "walker")
+//           destroy()
+//       }
+//   }
+//
+// Fields and destroy() can only be used in on_update, as only that hook has a target.
+pub struct ScriptBehaviour {
+    script: String,
+    components: Vec<NameTag>,
+    without: Vec<NameTag>,
+    added: Vec<NameTag>,
+    changed: Vec<NameTag>,
+    startup: Option<Hook>,
+    early_update: Option<Hook>,
+    update: Option<Hook>,
+}
+
+impl ScriptBehaviour {
+
+    // parses the script and checks components, fields and calls against the world
+    pub fn compile<T: Default + Reflect>(script: &str, source: &str, component_refs: &ComponentRefs) -> Result<Self, ScriptError> {
+        let tokens = tokenize(script, source)?;
+        let probe = T::default();
+        let mut parser = Parser { script, tokens, at: 0, locals: Vec::new(), slots: 0, has_target: false, probe: &probe };
+
+        let mut behaviour = ScriptBehaviour {
+            script: script.to_string(),
+            components: Vec::new(),
+            without: Vec::new(),
+            added: Vec::new(),
+            changed: Vec::new(),
+            startup: None,
+            early_update: None,
+            update: None,
+        };
+
+        while let Some(token) = parser.next() {
+            let line = token.line;
+            let word = match token.kind {
+                Tok::Ident(word) => word,
+                other => return Err(parser.error(line, format!("expected a hook or a component list, found {}", other))),
+            };
+            match word.as_str() {
+                "components" | "without" | "added" | "changed" => {
+                    let names = parser.component_list()?;
+                    for (name, line) in names {
+                        if component_refs.get(&NameTag::from_str(&name)).is_none() {
+                            return Err(parser.error(line, format!("unknown component '{}'", name)));
+                        }
+                        let tag = NameTag::from_str(&name);
+                        match word.as_str() {
+                            "components" => behaviour.components.push(tag),
+                            "without" => behaviour.without.push(tag),
+                            "added" => behaviour.added.push(tag),
+                            _ => behaviour.changed.push(tag),
+                        }
+                    }
+                },
+                "on_startup" | "on_early_update" | "on_update" => {
+                    let hook = match word.as_str() {
+                        "on_startup" => &mut behaviour.startup,
+                        "on_early_update" => &mut behaviour.early_update,
+                        _ => &mut behaviour.update,
+                    };
+                    if hook.is_some() {
+                        return Err(parser.error(line, format!("'{}' is defined twice", word)));
+                    }
+                    parser.has_target = word == "on_update";
+                    parser.slots = 0;
+                    let body = parser.block()?;
+                    *hook = Some(Hook { body, slots: parser.slots });
+                },
+                _ => return Err(parser.error(line, format!("expected a hook or a component list, found '{}'", word))),
+            }
+        }
+        Ok(behaviour)
+    }
+
+    pub fn read<T: Default + Reflect, P: AsRef<Path>>(path: P, component_refs: &ComponentRefs) -> Result<Self, ScriptError> {
+        let script = path.as_ref().display().to_string();
+        match fs::read_to_string(path.as_ref()) {
+            Ok(source) => Self::compile::<T>(&script, &source, component_refs),
+            Err(e) => Err(ScriptError::new(&script, 0, format!("could not read file: {}", e))),
+        }
+    }
+
+    pub fn script(&self) -> &str {
+        &self.script
+    }
+
+    // runtime errors such as a type mismatch stop the hook at the failing line
+    fn run<T: Default + Debug + Reflect>(&self, hook: &Hook, target: Option<&ObjectIndex>, objects: &mut Objects<T>, system: &mut System) -> Result<(), ScriptError> {
+        let mut frame = Frame { locals: vec![Value::Int(0); hook.slots], target, objects, system };
+        frame.block(&hook.body).map_err(|(line, message)| ScriptError::new(&self.script, line, message))
+    }
+
+    // a failing script disables its system, keeping the error for System::error
+    fn run_or_fail<T: Default + Debug + Reflect>(&self, hook: &Option<Hook>, target: Option<&ObjectIndex>, objects: &mut Objects<T>, system: &mut System) {
+        if let (Some(hook), true) = (hook, system.enabled) {
+            if let Err(error) = self.run(hook, target, objects, system) {
+                system.fail(error.to_string());
+            }
+        }
+    }
+}
+
+impl<T: Default + Debug + Reflect> Behaviour<T> for ScriptBehaviour {
+    fn required_components(&self) -> Vec<NameTag> {
+        self.components.clone()
+    }
+
+    fn access(&self) -> Access {
+        Access {
+            writes: self.components.clone(),
+            without: self.without.clone(),
+            added: self.added.clone(),
+            changed: self.changed.clone(),
+            ..Access::default()
+        }
+    }
+
+    fn on_startup(&mut self, objects: &mut Objects<T>, system: &mut System) {
+        self.run_or_fail(&self.startup, None, objects, system)
+    }
+
+    fn on_early_update(&mut self, objects: &mut Objects<T>, system: &mut System) {
+        self.run_or_fail(&self.early_update, None, objects, system)
+    }
+
+    fn on_update(&mut self, target: &ObjectIndex, objects: &mut Objects<T>, system: &mut System) {
+        self.run_or_fail(&self.update, Some(target), objects, system)
+    }
+
+    fn updates_entities(&self) -> bool {
+        self.update.is_some()
+    }
+}
+
+
+struct Hook {
+    body: Vec<Stmt>,
+    slots: usize, // locals used by the hook
+}
+
+enum Stmt {
+    SetLocal(usize, Expr, usize),
+    SetField(String, Expr, usize),
+    If(Expr, Vec<Stmt>, Vec<Stmt>, usize),
+    Call(Expr, usize),
+}
+
+#[derive(Clone)]
+enum Expr {
+    Literal(Value),
+    Local(usize),
+    Field(String),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(Op, Box<Expr>, Box<Expr>),
+    Call(Builtin, Vec<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Op { Or, And, Eq, Ne, Lt, Le, Gt, Ge, Add, Sub, Mul, Div, Rem }
+
+impl Op {
+    fn symbol(self) -> &'static str {
+        match self {
+            Op::Or => "or", Op::And => "and",
+            Op::Eq => "==", Op::Ne => "!=", Op::Lt => "<", Op::Le => "<=", Op::Gt => ">", Op::Ge => ">=",
+            Op::Add => "+", Op::Sub => "-", Op::Mul => "*", Op::Div => "/", Op::Rem => "%",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Builtin { Spawn, Destroy, Abs, Min, Max, Sqrt, Floor }
+
+impl Builtin {
+    fn lookup(name: &str) -> Option<(Builtin, usize)> {
+        match name {
+            "spawn" => Some((Builtin::Spawn, 2)),
+            "destroy" => Some((Builtin::Destroy, 0)),
+            "abs" => Some((Builtin::Abs, 1)),
+            "min" => Some((Builtin::Min, 2)),
+            "max" => Some((Builtin::Max, 2)),
+            "sqrt" => Some((Builtin::Sqrt, 1)),
+            "floor" => Some((Builtin::Floor, 1)),
+            _ => None,
+        }
+    }
+}
+
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    Sym(&'static str),
+}
+
+impl fmt::Display for Tok {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Tok::Ident(s) => write!(f, "'{}'", s),
+            Tok::Int(i) => write!(f, "'{}'", i),
+            Tok::Float(x) => write!(f, "'{}'", x),
+            Tok::Str(s) => write!(f, "\"{}\"", s),
+            Tok::Sym(s) => write!(f, "'{}'", s),
+        }
+    }
+}
+
+struct Token {
+    kind: Tok,
+    line: usize,
+}
+
+// longest symbols first, so '<=' isn't read as '<' followed by '='
+const SYMBOLS: [&str; 21] = [
+    "==", "!=", "<=", ">=", "+=", "-=", "*=", "/=",
+    "<", ">", "=", "+", "-", "*", "/", "%", "(", ")", "{", "}", ",",
+];
+
+fn tokenize(script: &str, source: &str) -> Result<Vec<Token>, ScriptError> {
+    let mut tokens = Vec::new();
+
+    for (i, raw) in source.lines().enumerate() {
+        let line = i + 1;
+        let chars: Vec<char> = raw.chars().collect();
+        let mut at = 0;
+
+        while at < chars.len() {
+            let c = chars[at];
+            let start = at;
+
+            if c == '#' {
+                break;
+            } else if c.is_whitespace() || c == ';' {
+                at += 1;
+            } else if c.is_ascii_digit() {
+                while at < chars.len() && (chars[at].is_ascii_digit() || chars[at] == '.' || chars[at] == '_') { at += 1; }
+                let number: String = chars[start..at].iter().filter(|c| **c != '_').collect();
+                let kind = if number.contains('.') {
+                    number.parse::<f64>().map(Tok::Float).map_err(|e| e.to_string())
+                } else {
+                    number.parse::<i64>().map(Tok::Int).map_err(|e| e.to_string())
+                };
+                match kind {
+                    Ok(kind) => tokens.push(Token { kind, line }),
+                    Err(e) => return Err(ScriptError::new(script, line, format!("invalid number '{}': {}", number, e))),
+                }
+            } else if c.is_alphabetic() || c == '_' {
+                while at < chars.len() && (chars[at].is_alphanumeric() || chars[at] == '_' || chars[at] == '.') { at += 1; }
+                tokens.push(Token { kind: Tok::Ident(chars[start..at].iter().collect()), line });
+            } else if c == '"' {
+                at += 1;
+                while at < chars.len() && chars[at] != '"' { at += 1; }
+                if at == chars.len() {
+                    return Err(ScriptError::new(script, line, "unterminated string".to_string()));
+                }
+                tokens.push(Token { kind: Tok::Str(chars[start + 1..at].iter().collect()), line });
+                at += 1;
+            } else {
+                let rest: String = chars[at..chars.len().min(at + 2)].iter().collect();
+                match SYMBOLS.iter().find(|s| rest.starts_with(**s)) {
+                    Some(symbol) => {
+                        tokens.push(Token { kind: Tok::Sym(symbol), line });
+                        at += symbol.len();
+                    },
+                    None => return Err(ScriptError::new(script, line, format!("unexpected character '{}'", c))),
+                }
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+
+struct Parser<'s, T> {
+    script: &'s str,
+    tokens: Vec<Token>,
+    at: usize,
+    locals: Vec<(String, usize)>, // names in scope and their slot
+    slots: usize,
+    has_target: bool,
+    probe: &'s T, // default object to check field paths against
+}
+
+impl<'s, T: Reflect> Parser<'s, T> {
+
+    fn error(&self, line: usize, message: String) -> ScriptError {
+        ScriptError::new(self.script, line, message)
+    }
+
+    fn line(&self) -> usize {
+        self.tokens.get(self.at).or(self.tokens.last()).map_or(0, |t| t.line)
+    }
+
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.at).map(|t| &t.kind)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.at).map(|t| Token { kind: t.kind.clone(), line: t.line });
+        self.at += 1;
+        token
+    }
+
+    fn eat(&mut self, symbol: &str) -> bool {
+        let found = match self.peek() {
+            Some(Tok::Sym(s)) => *s == symbol,
+            Some(Tok::Ident(s)) => s == symbol,
+            _ => false,
+        };
+        if found {
+            self.at += 1;
+        }
+        found
+    }
+
+    fn expect(&mut self, symbol: &str) -> Result<(), ScriptError> {
+        if self.eat(symbol) {
+            return Ok(());
+        }
+        let found = self.peek().map_or("end of script".to_string(), |t| t.to_string());
+        Err(self.error(self.line(), format!("expected '{}', found {}", symbol, found)))
+    }
+
+    fn component_list(&mut self) -> Result<Vec<(String, usize)>, ScriptError> {
+        let mut names = Vec::new();
+        loop {
+            let line = self.line();
+            match self.next().map(|t| t.kind) {
+                Some(Tok::Ident(name)) | Some(Tok::Str(name)) => names.push((name, line)),
+                _ => return Err(self.error(line, "expected a component name".to_string())),
+            }
+            if !self.eat(",") {
+                return Ok(names);
+            }
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, ScriptError> {
+        self.expect("{")?;
+        let scope = self.locals.len();
+        let mut body = Vec::new();
+        while !self.eat("}") {
+            if self.peek().is_none() {
+                return Err(self.error(self.line(), "missing '}'".to_string()));
+            }
+            body.push(self.statement()?);
+        }
+        self.locals.truncate(scope);
+        Ok(body)
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ScriptError> {
+        let line = self.line();
+        let name = match self.next().map(|t| t.kind) {
+            Some(Tok::Ident(name)) => name,
+            Some(other) => return Err(self.error(line, format!("expected a statement, found {}", other))),
+            None => return Err(self.error(line, "expected a statement".to_string())),
+        };
+
+        if name == "let" {
+            let local = match self.next().map(|t| t.kind) {
+                Some(Tok::Ident(local)) if !local.contains('.') => local,
+                _ => return Err(self.error(line, "expected a name after 'let'".to_string())),
+            };
+            self.expect("=")?;
+            let value = self.expression()?;
+            let slot = self.slots;
+            self.slots += 1;
+            self.locals.push((local, slot));
+            return Ok(Stmt::SetLocal(slot, value, line));
+        }
+
+        if name == "if" {
+            let condition = self.expression()?;
+            let then = self.block()?;
+            let otherwise = if self.eat("else") {
+                if self.peek() == Some(&Tok::Ident("if".to_string())) {
+                    vec![self.statement()?]
+                } else {
+                    self.block()?
+                }
+            } else {
+                Vec::new()
+            };
+            return Ok(Stmt::If(condition, then, otherwise, line));
+        }
+
+        if self.peek() == Some(&Tok::Sym("(")) {
+            return Ok(Stmt::Call(self.call(&name, line)?, line));
+        }
+
+        let target = self.variable(&name, line)?;
+        let op = match self.next().map(|t| t.kind) {
+            Some(Tok::Sym("=")) => None,
+            Some(Tok::Sym("+=")) => Some(Op::Add),
+            Some(Tok::Sym("-=")) => Some(Op::Sub),
+            Some(Tok::Sym("*=")) => Some(Op::Mul),
+            Some(Tok::Sym("/=")) => Some(Op::Div),
+            _ => return Err(self.error(line, format!("expected an assignment to '{}'", name))),
+        };
+        let mut value = self.expression()?;
+        if let Some(op) = op {
+            value = Expr::Binary(op, Box::new(target.clone()), Box::new(value));
+        }
+        match target {
+            Expr::Local(slot) => Ok(Stmt::SetLocal(slot, value, line)),
+            Expr::Field(path) => Ok(Stmt::SetField(path, value, line)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn variable(&self, name: &str, line: usize) -> Result<Expr, ScriptError> {
+        if let Some((_, slot)) = self.locals.iter().rev().find(|l| l.0 == name) {
+            return Ok(Expr::Local(*slot));
+        }
+        if !self.has_target {
+            return Err(self.error(line, format!("'{}' is not a local and fields are only available in on_update", name)));
+        }
+        if self.probe.get_path(name).is_none() {
+            return Err(self.error(line, format!("unknown field '{}'", name)));
+        }
+        Ok(Expr::Field(name.to_string()))
+    }
+
+    fn call(&mut self, name: &str, line: usize) -> Result<Expr, ScriptError> {
+        let (builtin, arity) = match Builtin::lookup(name) {
+            Some(found) => found,
+            None => return Err(self.error(line, format!("unknown function '{}'", name))),
+        };
+        if builtin == Builtin::Destroy && !self.has_target {
+            return Err(self.error(line, "destroy() is only available in on_update".to_string()));
+        }
+        self.expect("(")?;
+        let mut args = Vec::new();
+        if !self.eat(")") {
+            loop {
+                args.push(self.expression()?);
+                if self.eat(")") { break; }
+                self.expect(",")?;
+            }
+        }
+        if args.len() != arity {
+            return Err(self.error(line, format!("{}() takes {} arguments, found {}", name, arity, args.len())));
+        }
+        Ok(Expr::Call(builtin, args))
+    }
+
+    // precedence climbing, from loosest to tightest binding
+    fn expression(&mut self) -> Result<Expr, ScriptError> {
+        self.binary(0)
+    }
+
+    fn binary(&mut self, level: usize) -> Result<Expr, ScriptError> {
+        const LEVELS: [&[Op]; 5] = [
+            &[Op::Or],
+            &[Op::And],
+            &[Op::Eq, Op::Ne, Op::Le, Op::Ge, Op::Lt, Op::Gt],
+            &[Op::Add, Op::Sub],
+            &[Op::Mul, Op::Div, Op::Rem],
+        ];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+        let mut left = self.binary(level + 1)?;
+        'outer: loop {
+            for op in LEVELS[level] {
+                if self.eat(op.symbol()) {
+                    let right = self.binary(level + 1)?;
+                    left = Expr::Binary(*op, Box::new(left), Box::new(right));
+                    continue 'outer;
+                }
+            }
+            return Ok(left);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ScriptError> {
+        if self.eat("not") {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat("-") {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        let line = self.line();
+        match self.next().map(|t| t.kind) {
+            Some(Tok::Int(i)) => Ok(Expr::Literal(Value::Int(i))),
+            Some(Tok::Float(x)) => Ok(Expr::Literal(Value::Float(x))),
+            Some(Tok::Str(s)) => Ok(Expr::Literal(Value::Str(s))),
+            Some(Tok::Sym("(")) => {
+                let inner = self.expression()?;
+                self.expect(")")?;
+                Ok(inner)
+            },
+            Some(Tok::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                _ if self.peek() == Some(&Tok::Sym("(")) => self.call(&name, line),
+                _ => self.variable(&name, line),
+            },
+            Some(other) => Err(self.error(line, format!("expected a value, found {}", other))),
+            None => Err(self.error(line, "expected a value".to_string())),
+        }
+    }
+}
+
+
+struct Frame<'f, T: Default> {
+    locals: Vec<Value>,
+    target: Option<&'f ObjectIndex>,
+    objects: &'f mut Objects<T>,
+    system: &'f mut System,
+}
+
+type Run<V> = Result<V, (usize, String)>;
+
+impl<'f, T: Default + Debug + Reflect> Frame<'f, T> {
+
+    fn block(&mut self, body: &[Stmt]) -> Run<()> {
+        for stmt in body {
+            match stmt {
+                Stmt::SetLocal(slot, expr, line) => {
+                    self.locals[*slot] = self.eval(expr).map_err(|e| (*line, e))?;
+                },
+                Stmt::SetField(path, expr, line) => {
+                    let value = self.eval(expr).map_err(|e| (*line, e))?;
+                    let target = self.target.expect("fields are checked to be used in on_update only");
+                    self.objects.get_mut(target).set_path(path, value).map_err(|e| (*line, e.to_string()))?;
+                },
+                Stmt::If(condition, then, otherwise, line) => {
+                    match self.eval(condition).map_err(|e| (*line, e))? {
+                        Value::Bool(true) => self.block(then)?,
+                        Value::Bool(false) => self.block(otherwise)?,
+                        other => return Err((*line, format!("if expects a bool, found {}", other.type_name()))),
+                    }
+                },
+                Stmt::Call(expr, line) => {
+                    self.eval(expr).map_err(|e| (*line, e))?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Result<Value, String> {
+        match expr {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Local(slot) => Ok(self.locals[*slot].clone()),
+            Expr::Field(path) => {
+                let target = self.target.expect("fields are checked to be used in on_update only");
+                self.objects.get_ref(target).get_path(path).ok_or_else(|| format!("unknown field '{}'", path))
+            },
+            Expr::Not(inner) => match self.eval(inner)? {
+                Value::Bool(b) => Ok(Value::Bool(!b)),
+                other => Err(format!("'not' expects a bool, found {}", other.type_name())),
+            },
+            Expr::Negate(inner) => match self.eval(inner)? {
+                Value::Int(i) => Ok(Value::Int(i.wrapping_neg())),
+                Value::Float(x) => Ok(Value::Float(-x)),
+                other => Err(format!("'-' expects a number, found {}", other.type_name())),
+            },
+            Expr::Binary(Op::And, left, right) => match self.eval(left)? {
+                Value::Bool(false) => Ok(Value::Bool(false)),
+                Value::Bool(true) => self.eval(right),
+                other => Err(format!("'and' expects a bool, found {}", other.type_name())),
+            },
+            Expr::Binary(Op::Or, left, right) => match self.eval(left)? {
+                Value::Bool(true) => Ok(Value::Bool(true)),
+                Value::Bool(false) => self.eval(right),
+                other => Err(format!("'or' expects a bool, found {}", other.type_name())),
+            },
+            Expr::Binary(op, left, right) => {
+                let left = self.eval(left)?;
+                let right = self.eval(right)?;
+                binary(*op, left, right)
+            },
+            Expr::Call(builtin, args) => {
+                let mut values = Vec::with_capacity(args.len());
+                for arg in args {
+                    values.push(self.eval(arg)?);
+                }
+                self.call(*builtin, values)
+            },
+        }
+    }
+
+    fn call(&mut self, builtin: Builtin, args: Vec<Value>) -> Result<Value, String> {
+        let number = |value: &Value| value.as_float().ok_or_else(|| format!("expected a number, found {}", value.type_name()));
+
+        match builtin {
+            Builtin::Spawn => match (&args[0], &args[1]) {
+                (Value::Str(name), Value::Str(type_of)) => {
+                    self.system.spawn(name, type_of);
+                    Ok(Value::Bool(true))
+                },
+                _ => Err("spawn() expects a name and a factory string".to_string()),
+            },
+            Builtin::Destroy => {
+                let target = self.target.expect("destroy is checked to be used in on_update only");
+                self.system.destroy(target);
+                Ok(Value::Bool(true))
+            },
+            Builtin::Abs => match &args[0] {
+                Value::Int(i) => Ok(Value::Int(i.wrapping_abs())),
+                other => Ok(Value::Float(number(other)?.abs())),
+            },
+            Builtin::Min | Builtin::Max => {
+                let pick_left = match builtin {
+                    Builtin::Min => number(&args[0])? <= number(&args[1])?,
+                    _ => number(&args[0])? >= number(&args[1])?,
+                };
+                Ok(if pick_left { args[0].clone() } else { args[1].clone() })
+            },
+            Builtin::Sqrt => Ok(Value::Float(number(&args[0])?.sqrt())),
+            Builtin::Floor => Ok(Value::Int(number(&args[0])?.floor() as i64)),
+        }
+    }
+}
+
+fn binary(op: Op, left: Value, right: Value) -> Result<Value, String> {
+    let mismatch = || format!("can't apply '{}' to {} and {}", op.symbol(), left.type_name(), right.type_name());
+
+    match (op, &left, &right) {
+        (Op::Eq, _, _) | (Op::Ne, _, _) => {
+            let equal = match (left.as_float(), right.as_float()) {
+                (Some(a), Some(b)) => a == b,
+                _ => left == right,
+            };
+            Ok(Value::Bool(equal == (op == Op::Eq)))
+        },
+        (Op::Add, Value::Str(a), b) => Ok(Value::Str(format!("{}{}", a, b))),
+        (Op::Add, a, Value::Str(b)) => Ok(Value::Str(format!("{}{}", a, b))),
+        (_, Value::Int(a), Value::Int(b)) => match op {
+            Op::Add => Ok(Value::Int(a.wrapping_add(*b))),
+            Op::Sub => Ok(Value::Int(a.wrapping_sub(*b))),
+            Op::Mul => Ok(Value::Int(a.wrapping_mul(*b))),
+            Op::Div | Op::Rem if *b == 0 => Err("division by zero".to_string()),
+            Op::Div => a.checked_div(*b).map(Value::Int).ok_or_else(|| "integer overflow in '/'".to_string()),
+            Op::Rem => a.checked_rem(*b).map(Value::Int).ok_or_else(|| "integer overflow in '%'".to_string()),
+            _ => compare(op, *a as f64, *b as f64).ok_or_else(mismatch),
+        },
+        _ => match (left.as_float(), right.as_float()) {
+            (Some(a), Some(b)) => match op {
+                Op::Add => Ok(Value::Float(a + b)),
+                Op::Sub => Ok(Value::Float(a - b)),
+                Op::Mul => Ok(Value::Float(a * b)),
+                Op::Div => Ok(Value::Float(a / b)),
+                Op::Rem => Ok(Value::Float(a % b)),
+                _ => compare(op, a, b).ok_or_else(mismatch),
+            },
+            _ => Err(mismatch()),
+        },
+    }
+}
+
+fn compare(op: Op, a: f64, b: f64) -> Option<Value> {
+    let result = match op {
+        Op::Lt => a < b,
+        Op::Le => a <= b,
+        Op::Gt => a > b,
+        Op::Ge => a >= b,
+        _ => return None,
+    };
+    Some(Value::Bool(result))
+}
+
+
+impl<T: Default + Debug + Reflect + 'static> SystemBuilder<T> {
+
+    pub fn define_script(self, script: &str, source: &str) -> Result<Self, ScriptError> {
+        let behaviour = ScriptBehaviour::compile::<T>(script, source, &self.component_refs)?;
+        Ok(self.define_system(Box::new(behaviour)))
+    }
+
+    pub fn load_script<P: AsRef<Path>>(self, path: P) -> Result<Self, ScriptError> {
+        let behaviour = ScriptBehaviour::read::<T, P>(path, &self.component_refs)?;
+        Ok(self.define_system(Box::new(behaviour)))
+    }
+}
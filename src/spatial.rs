@@ -0,0 +1,176 @@
+use std::collections::HashMap;
+
+use super::{ ObjectIndex, ComponentIndex, BitFlags };
+
+type PositionFn<T> = Box<dyn Fn(&T) -> (f32, f32)>;
+type Cell = (i32, i32);
+
+
+// Uniform grid over the objects holding the position component. Objects are marked
+// when they are spawned, destroyed, gain or lose the component or are borrowed through
+// get_mut, and are put into their new cell by the next query.
+pub(crate) struct SpatialIndex<T> {
+    component: ComponentIndex,
+    cell_size: f32,
+    position: PositionFn<T>,
+    grid: HashMap<Cell, Vec<ObjectIndex>>,
+    tracked: Vec<bool>, // alive and holding the component
+    placed: Vec<Option<(Cell, (f32, f32))>>, // where every slot is in the grid
+    dirty: Vec<ObjectIndex>,
+    marked: Vec<bool>,
+    count: usize, // objects in the grid
+}
+
+impl<T> SpatialIndex<T> {
+    pub(crate) fn new(component: ComponentIndex, cell_size: f32, position: PositionFn<T>, size: usize) -> Self {
+        SpatialIndex {
+            component,
+            cell_size: cell_size.max(f32::EPSILON),
+            position,
+            grid: HashMap::new(),
+            tracked: vec![false; size],
+            placed: vec![None; size],
+            dirty: Vec::new(),
+            marked: vec![false; size],
+            count: 0,
+        }
+    }
+
+    pub(crate) fn grow(&mut self, new_size: usize) {
+        self.tracked.resize(new_size, false);
+        self.placed.resize(new_size, None);
+        self.marked.resize(new_size, false);
+    }
+
+    // the object moved or may have
+    #[inline]
+    pub(crate) fn mark(&mut self, target: ObjectIndex) {
+        if (self.tracked[target] || self.placed[target].is_some()) && !self.marked[target] {
+            self.marked[target] = true;
+            self.dirty.push(target);
+        }
+    }
+
+    // after a spawn or a change of components, flags are empty for destroyed objects
+    pub(crate) fn track(&mut self, target: ObjectIndex, flags: &BitFlags) {
+        self.tracked[target] = flags.bit(self.component);
+        self.mark(target);
+    }
+
+    pub(crate) fn sync(&mut self, pool: &[T]) {
+        for target in std::mem::take(&mut self.dirty) {
+            self.marked[target] = false;
+            let old = self.placed[target];
+            let new = match self.tracked[target] {
+                true => {
+                    let position = (self.position)(&pool[target]);
+                    Some((self.cell_of(position), position))
+                },
+                false => None,
+            };
+            match (old, new) {
+                (Some((from, _)), Some((to, _))) if from == to => {},
+                (old, new) => {
+                    if let Some((from, _)) = old {
+                        self.take_out(from, target);
+                    }
+                    if let Some((to, _)) = new {
+                        self.grid.entry(to).or_default().push(target);
+                        self.count += 1;
+                    }
+                },
+            }
+            self.placed[target] = new;
+        }
+    }
+
+    fn take_out(&mut self, cell: Cell, target: ObjectIndex) {
+        if let Some(members) = self.grid.get_mut(&cell) {
+            if let Some(at) = members.iter().position(|m| *m == target) {
+                members.swap_remove(at);
+                self.count -= 1;
+            }
+            if members.is_empty() {
+                self.grid.remove(&cell);
+            }
+        }
+    }
+
+    fn cell_of(&self, (x, y): (f32, f32)) -> Cell {
+        ((x / self.cell_size).floor() as i32, (y / self.cell_size).floor() as i32)
+    }
+
+    // objects whose position lies within the rectangle, borders included
+    pub(crate) fn aabb(&self, min: (f32, f32), max: (f32, f32)) -> Vec<ObjectIndex> {
+        let (low, high) = (self.cell_of(min), self.cell_of(max));
+        let inside = |target: &ObjectIndex| {
+            let (x, y) = self.placed[*target].unwrap().1;
+            x >= min.0 && x <= max.0 && y >= min.1 && y <= max.1
+        };
+        let mut found: Vec<ObjectIndex> = Vec::new();
+        let span = (high.0 as i64 - low.0 as i64 + 1) * (high.1 as i64 - low.1 as i64 + 1);
+        if span > self.grid.len() as i64 {
+            // a large area, the occupied cells are fewer than the cells it covers
+            found.extend(self.grid.values().flatten().filter(|t| inside(t)));
+        } else {
+            for cx in low.0..=high.0 {
+                for cy in low.1..=high.1 {
+                    found.extend(self.grid.get(&(cx, cy)).into_iter().flatten().filter(|t| inside(t)));
+                }
+            }
+        }
+        found.sort_unstable();
+        found
+    }
+
+    pub(crate) fn radius(&self, center: (f32, f32), radius: f32) -> Vec<ObjectIndex> {
+        let mut found = self.aabb((center.0 - radius, center.1 - radius), (center.0 + radius, center.1 + radius));
+        found.retain(|target| distance(self.placed[*target].unwrap().1, center) <= radius * radius);
+        found
+    }
+
+    // searches rings of cells around the point until nothing further out can be closer
+    pub(crate) fn nearest(&self, point: (f32, f32), count: usize) -> Vec<ObjectIndex> {
+        let center = self.cell_of(point);
+        let mut candidates: Vec<(f32, ObjectIndex)> = Vec::new();
+        let mut seen = 0;
+        let mut ring = 0;
+        while seen < self.count && count > 0 {
+            // rings that cover more cells than are occupied cost more than looking at all
+            let cells = (2 * ring as usize + 1).pow(2);
+            if cells > self.grid.len() * 2 {
+                candidates = self.grid.values().flatten()
+                    .map(|target| (distance(self.placed[*target].unwrap().1, point), *target))
+                    .collect();
+                candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+                candidates.truncate(count);
+                break;
+            }
+            for cx in center.0 - ring..=center.0 + ring {
+                for cy in center.1 - ring..=center.1 + ring {
+                    if (cx - center.0).abs() != ring && (cy - center.1).abs() != ring {
+                        continue;
+                    }
+                    for target in self.grid.get(&(cx, cy)).into_iter().flatten() {
+                        candidates.push((distance(self.placed[*target].unwrap().1, point), *target));
+                        seen += 1;
+                    }
+                }
+            }
+            candidates.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+            candidates.truncate(count);
+            // everything beyond this ring is at least `ring` cells away
+            let reach = ring as f32 * self.cell_size;
+            if candidates.len() == count && candidates[count - 1].0 <= reach * reach {
+                break;
+            }
+            ring += 1;
+        }
+        candidates.into_iter().map(|c| c.1).collect()
+    }
+}
+
+// squared
+fn distance(a: (f32, f32), b: (f32, f32)) -> f32 {
+    (a.0 - b.0) * (a.0 - b.0) + (a.1 - b.1) * (a.1 - b.1)
+}
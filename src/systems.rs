@@ -4,54 +4,228 @@ use super::pool::*;
 
 pub struct System {
     pub(crate) index: SystemIndex,
-    pub(crate) spawn_requests: Vec<(NameTag, String)>,
+    pub(crate) spawn_requests: Vec<(NameTag, String, SpawnArgs)>,
+    pub(crate) spawn_batch_requests: Vec<(String, usize)>,
     pub(crate) destroy_requests: Vec<ObjectIndex>,
     pub(crate) components: BitFlags,
+    pub(crate) excluded: BitFlags,
+    pub(crate) access: Access,
+    pub(crate) updates_entities: bool,
+    pub(crate) enabled: bool, // disabled systems are skipped by update
+    pub(crate) error: Option<String>, // why the system disabled itself, see System::fail
+    // change detection
+    pub(crate) last_run: u64, // tick the system last ran at
+    pub(crate) added: BitFlags,
+    pub(crate) changed: BitFlags,
+    pub(crate) writes: BitFlags, // components its mutable borrows count as changing
+    pub(crate) watched: Vec<(NameTag, ComponentIndex)>, // components to report removals of
+    pub(crate) removed: Vec<(ObjectIndex, ComponentIndex)>,
+    pub(crate) removals_from: u64, // tick the system was last enabled at
 }
 
 impl System {
 
-    pub(crate) fn new(index: SystemIndex, components: BitFlags) -> Self {
+    pub(crate) fn new(index: SystemIndex) -> Self {
         
         System {
             index,
             spawn_requests: Vec::new(),
+            spawn_batch_requests: Vec::new(),
             destroy_requests: Vec::new(),
-            components,
+            components: BitFlags (0),
+            excluded: BitFlags (0),
+            access: Access::default(),
+            updates_entities: true,
+            enabled: true,
+            error: None,
+            last_run: 0,
+            added: BitFlags (0),
+            changed: BitFlags (0),
+            writes: BitFlags (0),
+            watched: Vec::new(),
+            removed: Vec::new(),
+            removals_from: 0,
         }
     }
 
+    // takes over which entities to match from the behaviour, returns the names of
+    // components the behaviour refers to that were not defined
+    pub(crate) fn describe<T: Default>(&mut self, behaviour: &dyn Behaviour<T>, component_refs: &ComponentRefs) -> Vec<NameTag> {
+        self.components.reset();
+        self.excluded.reset();
+        self.added.reset();
+        self.changed.reset();
+        self.writes.reset();
+        self.watched.clear();
+
+        let mut unknown = Vec::new();
+        let mut lookup = |s: &NameTag| {
+            let found = component_refs.get(s).map(|c| *c.index());
+            if found.is_none() && !unknown.contains(s) {
+                unknown.push(s.clone());
+            }
+            found
+        };
+
+        for s in behaviour.required_components().iter() {
+            if let Some(c) = lookup(s) {
+                self.components.set_bit(c, true)
+            }
+        }
+        self.access = behaviour.access();
+
+        for s in self.access.reads.iter() {
+            lookup(s);
+        }
+        for s in self.access.writes.iter() {
+            if let Some(c) = lookup(s) {
+                self.writes.set_bit(c, true)
+            }
+        }
+        for s in self.access.without.iter() {
+            if let Some(c) = lookup(s) {
+                self.excluded.set_bit(c, true)
+            }
+        }
+        // filtered components have to be present as well
+        for s in self.access.added.iter() {
+            if let Some(c) = lookup(s) {
+                self.added.set_bit(c, true);
+                self.components.set_bit(c, true);
+            }
+        }
+        for s in self.access.changed.iter() {
+            if let Some(c) = lookup(s) {
+                self.changed.set_bit(c, true);
+                self.components.set_bit(c, true);
+            }
+        }
+        for s in self.access.removed.iter() {
+            if let Some(c) = lookup(s) {
+                self.watched.push((s.clone(), c));
+            }
+        }
+        self.updates_entities = behaviour.updates_entities();
+        unknown
+    }
+
+    // an entity matches when it has all required components and none of the excluded ones
+    #[inline]
+    pub(crate) fn matches(&self, entity: &BitFlags) -> bool {
+        entity.contains(&self.components) && entity.0 & self.excluded.0 == 0
+    }
+
+    // disables the system from within its behaviour, keeping the error until it is enabled again
+    pub fn fail(&mut self, error: String) {
+        self.enabled = false;
+        self.error = Some(error);
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        self.error.as_deref()
+    }
+
+    #[inline]
+    pub(crate) fn has_filters(&self) -> bool {
+        self.added.0 | self.changed.0 != 0
+    }
+
+    // added and changed components are judged against the last run of the system,
+    // an added component counts as a change too
+    pub(crate) fn passes_filters<T: Default>(&self, target: ObjectIndex, entities: &Entities, objects: &Objects<T>) -> bool {
+        let since = self.last_run;
+        let added = |component| entities.added_since(target, component, since);
+
+        let components = entities.columns.len();
+        (0..components).all(|c| !self.added.bit(c) || added(c))
+            && (0..components).all(|c| !self.changed.bit(c) || added(c) || objects.changed_since(target, c, since))
+    }
+
+    // removals before this tick were seen or happened while the system was disabled
+    pub(crate) fn removed_since(&self) -> u64 {
+        self.last_run.max(self.removals_from)
+    }
+
+    // takes the removals that happened since the last run of the system
+    pub(crate) fn collect_removed(&mut self, entities: &Entities) {
+        self.removed.clear();
+        if self.watched.is_empty() {
+            return;
+        }
+        let since = self.removed_since();
+        let watched = &self.watched;
+        self.removed.extend(entities.removed.iter()
+            .filter(|r| r.2 > since && watched.iter().any(|w| w.1 == r.1))
+            .map(|r| (r.0, r.1)));
+    }
+
+    // objects that lost the component, or were destroyed having it, since the last run
+    // of the system, only for components listed in Access::removed
+    pub fn removed(&self, component: &str) -> Vec<ObjectIndex> {
+        let component = match self.watched.iter().find(|w| w.0.as_str() == component) {
+            Some(w) => w.1,
+            None => return Vec::new(),
+        };
+        self.removed.iter().filter(|r| r.1 == component).map(|r| r.0).collect()
+    }
+
+    pub fn removed_of<K: ComponentKey>(&self) -> Vec<ObjectIndex> {
+        self.removed(K::NAME)
+    }
+
+    // the required components, as shown in profiler reports and world dumps
+    pub(crate) fn label(&self, component_refs: &ComponentRefs) -> String {
+        let names: Vec<&str> = component_refs.decode(&self.components).iter().map(|c| c.name().as_str()).collect();
+        format!("({})", names.join(", "))
+    }
+
+    pub fn last_run(&self) -> u64 {
+        self.last_run
+    }
+
+    pub fn access(&self) -> &Access {
+        &self.access
+    }
+
     pub(crate) fn handle_requests<'a, T: Default> (
         &mut self, 
         objects: &mut Objects<T>,
         entities: &mut Entities,
-        factories: &mut Vec<(String, Box<dyn Factory<'a, T>>)>,
+        factories: &mut Factories<'a, T>,
         component_refs: &ComponentRefs,
     ) {
         // destroy requests
-        while self.destroy_requests.len() > 0 {
-            if let Some(target) = self.destroy_requests.pop() {
-                super::destroy_object(
-                    &target,           
-                    entities,
-                    objects,
-                )
-            }
+        if !self.destroy_requests.is_empty() {
+            super::destroy_batch(
+                &self.destroy_requests,
+                entities,
+                objects,
+            );
+            self.destroy_requests.clear();
         }
         // spawn requests
-        while self.spawn_requests.len() > 0 {
-            if let Some(spawn) = self.spawn_requests.pop() {
-                super::create_object(
-                    spawn.0,
-                    &spawn.1,
-                    entities,
-                    objects,
-                    factories,
-                    &component_refs,
-                );
-            }
+        while let Some(spawn) = self.spawn_requests.pop() {
+            super::create_object(
+                spawn.0,
+                &spawn.1,
+                &spawn.2,
+                entities,
+                objects,
+                factories,
+                component_refs,
+            );
+        }
+        for (type_of, count) in self.spawn_batch_requests.drain(..) {
+            super::create_batch(
+                &type_of,
+                count,
+                &SpawnArgs::new(),
+                entities,
+                objects,
+                factories,
+                component_refs,
+            );
         }
-        
     }
 
     // pub(crate) fn add_component(&mut self, component: ComponentRef) {
@@ -59,17 +233,57 @@ impl System {
     // }
 
     pub fn spawn(&mut self, new_name: &str, type_of: &str) {
-        self.spawn_requests.push((NameTag::from_str(new_name), type_of.to_string()));
+        self.spawn_with(new_name, type_of, SpawnArgs::new());
+    }
+
+    // the args are kept with the request until it is handled after the update
+    pub fn spawn_with(&mut self, new_name: &str, type_of: &str, args: SpawnArgs) {
+        self.spawn_requests.push((NameTag::from_str(new_name), type_of.to_string(), args));
+    }
+
+    pub fn spawn_batch(&mut self, type_of: &str, count: usize) {
+        self.spawn_batch_requests.push((type_of.to_string(), count));
     }
 
     pub fn destroy(&mut self, target: &ObjectIndex) {
-        self.destroy_requests.push(target.clone());
+        self.destroy_requests.push(*target);
+    }
+
+    pub fn destroy_batch(&mut self, targets: &[ObjectIndex]) {
+        self.destroy_requests.extend_from_slice(targets);
     }
+
+    pub(crate) fn has_requests(&self) -> bool {
+        !self.destroy_requests.is_empty() 
+            || !self.spawn_requests.is_empty() 
+            || !self.spawn_batch_requests.is_empty()
+    }
+}
+
+// Which components a behaviour reads, writes or requires to be absent. Reads are
+// informative, the other lists change which entities are updated: `added` only lets
+// through entities that got the component since the system last ran, `changed` those
+// that got it or where it changed. A mutable borrow changes the components the borrowing
+// system writes, or every component when borrowed outside of a system. `removed` lists
+// components whose removals the system wants to see through System::removed.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Access {
+    pub reads: Vec<NameTag>,
+    pub writes: Vec<NameTag>,
+    pub without: Vec<NameTag>,
+    pub added: Vec<NameTag>,
+    pub changed: Vec<NameTag>,
+    pub removed: Vec<NameTag>,
 }
 
 pub trait Behaviour<T: Default> {
     fn required_components(&self) -> Vec<NameTag>;
 
+    // by default every required component is treated as written
+    fn access(&self) -> Access {
+        Access { writes: self.required_components(), ..Access::default() }
+    }
+
     #[allow(unused_variables)]
     fn on_startup(&mut self, objects: &mut Objects<T>, system: &mut System) {}
 
@@ -78,4 +292,75 @@ pub trait Behaviour<T: Default> {
 
     #[allow(unused_variables)]
     fn on_update(&mut self, target: &ObjectIndex, objects: &mut Objects<T>, system: &mut System);
+
+    // when false the system skips the per entity on_update loop
+    fn updates_entities(&self) -> bool { true }
+}
+
+
+type HookFn<T> = Box<dyn FnMut(&mut Objects<T>, &mut System)>;
+type UpdateFn<T> = Box<dyn FnMut(&ObjectIndex, &mut Objects<T>, &mut System)>;
+
+// Behaviour made out of closures, for systems too small to deserve their own type:
+//
+//   FnBehaviour::new(&["position", "velocity"])
+//       .on_update(|target, objects, system| { ... })
+//       .on_early_update(|objects, system| { ... })
+//
+pub struct FnBehaviour<T: Default> {
+    components: Vec<NameTag>,
+    startup: Option<HookFn<T>>,
+    early_update: Option<HookFn<T>>,
+    update: Option<UpdateFn<T>>,
+}
+
+impl<T: Default> FnBehaviour<T> {
+    pub fn new(components: &[&str]) -> Self {
+        FnBehaviour {
+            components: components.iter().map(|c| NameTag::from_str(c)).collect(),
+            startup: None,
+            early_update: None,
+            update: None,
+        }
+    }
+
+    pub fn on_startup<F>(mut self, hook: F) -> Self
+    where F: FnMut(&mut Objects<T>, &mut System) + 'static {
+        self.startup = Some(Box::new(hook));
+        self
+    }
+
+    pub fn on_early_update<F>(mut self, hook: F) -> Self
+    where F: FnMut(&mut Objects<T>, &mut System) + 'static {
+        self.early_update = Some(Box::new(hook));
+        self
+    }
+
+    pub fn on_update<F>(mut self, hook: F) -> Self
+    where F: FnMut(&ObjectIndex, &mut Objects<T>, &mut System) + 'static {
+        self.update = Some(Box::new(hook));
+        self
+    }
+}
+
+impl<T: Default> Behaviour<T> for FnBehaviour<T> {
+    fn required_components(&self) -> Vec<NameTag> {
+        self.components.clone()
+    }
+
+    fn on_startup(&mut self, objects: &mut Objects<T>, system: &mut System) {
+        if let Some(hook) = &mut self.startup { hook(objects, system) }
+    }
+
+    fn on_early_update(&mut self, objects: &mut Objects<T>, system: &mut System) {
+        if let Some(hook) = &mut self.early_update { hook(objects, system) }
+    }
+
+    fn on_update(&mut self, target: &ObjectIndex, objects: &mut Objects<T>, system: &mut System) {
+        if let Some(hook) = &mut self.update { hook(target, objects, system) }
+    }
+
+    fn updates_entities(&self) -> bool {
+        self.update.is_some()
+    }
 }
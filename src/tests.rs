@@ -1,133 +1,27 @@
-
-#[cfg(test)]
-use std::time::SystemTime;
+use std::rc::Rc;
 use super::*;
-
-static mut TEST_EARLY: u128 = 0;
+use super::pool::MAX_REMOVED;
 
 #[test]
 fn early_update() {
-    unsafe { 
-
-        let mut ecs = EcsBuilder::new(10)
-                .define_component("call-1")
-            .build_systems()
-                .define_system(Box::new(Call1))
-            .setup_factories()
-                .define_factory("type-1", Box::new(Factory1))
-            .finalize();
-        
-        TEST_EARLY = 0;
-        assert_eq!(TEST_EARLY, 0);
-
-        ecs.start();
-        assert_eq!(TEST_EARLY, 1);
-
-        ecs.update();
-        assert_eq!(TEST_EARLY, 2);
-    }
-}
-
-#[test]
-fn update_speed() {
-
+    let call1 = Call1::default();
+    let early = call1.early.clone();
     let mut ecs = EcsBuilder::new(10)
             .define_component("call-1")
-            .define_component("call-2")
-            .define_component("call-3")
-        .build_systems()
-
-            .define_system(Box::new(Call1))
-            .define_system(Box::new(Call2))
-            .define_system(Box::new(Call3))
-        .setup_factories()
-
-            .define_factory("type-1", Box::new(Factory1))
-            .define_factory("type-2", Box::new(Factory2))
-            .define_factory("type-3", Box::new(Factory3))
-        .finalize();
-
-
-    ecs.spawn("entity-1", "type-1");
-    ecs.spawn("entity-2", "type-2");
-    ecs.spawn("entity-3", "type-3");
-    ecs.start();
-
-    let num_updates: u128 = 10_000_000;
-    let num_calls: f64 = 4.0 * num_updates as f64;
-    let now = SystemTime::now();
-
-    for _i in 0..num_updates { ecs.update(); }
-
-    let elapsed_res = now.elapsed();
-    match elapsed_res {
-        Ok(elapsed) => println!("updates {} Mil calls/s", 
-            num_calls / (elapsed.as_nanos() as f64 / 1_000.0)),
-        Err(e)      => println!("Error: {:?}", e),
-    }
-
-    if let Some(entity1) = ecs.find("entity-1") {
-        println!(" - result 1: {}, {}, {}", 
-            ecs.get_ref(&entity1).call1.clone(), 
-            ecs.get_ref(&entity1).call2.clone(), 
-            ecs.get_ref(&entity1).call3.clone()
-        );
-    }
-    if let Some(entity2) = ecs.find("entity-2") {
-        println!(" - result 2: {}, {}, {}", 
-            ecs.get_ref(&entity2).call1.clone(), 
-            ecs.get_ref(&entity2).call2.clone(), 
-            ecs.get_ref(&entity2).call3.clone()
-        );
-    }if let Some(entity3) = ecs.find("entity-3") {
-        println!(" - result 3: {}, {}, {}", 
-            ecs.get_ref(&entity3).call1.clone(), 
-            ecs.get_ref(&entity3).call2.clone(), 
-            ecs.get_ref(&entity3).call3.clone()
-        );
-    }if let Some(test) = ecs.find("test") {
-        println!(" - test: {}, {}, {}", 
-            ecs.get_ref(&test).call1.clone(), 
-            ecs.get_ref(&test).call2.clone(), 
-            ecs.get_ref(&test).call3.clone()
-        );
-    }
-    assert!(false);
-}
-
-#[test]
-fn open_update_speed() {
-
-    let mut ecs:Ecs<Cell> = EcsBuilder::new(10)
         .build_systems()
+            .define_system(Box::new(call1))
         .setup_factories()
             .define_factory("type-1", Box::new(Factory1))
         .finalize();
 
-    ecs.spawn("entity-1", "type-1");
     ecs.start();
-    
-    let num_updates: u128 = 10_000_000;
-    let now = SystemTime::now();
-
-    for _i in 0..num_updates {
-        ecs.open_update(|target, pool| pool[*target].call1 += 1);
-    }
-
-    let elapsed_res = now.elapsed();
-    match elapsed_res {
-        Ok(elapsed) => println!("updates {} Mil calls/s", 
-        num_updates as f64 / (elapsed.as_nanos() / 1_000) as f64),
-        Err(e)      => println!("Error: {:?}", e),
-    }
+    assert_eq!(early.get(), 1);
 
-    if let Some(entity1) = ecs.objects.find("entity-1") {
-        println!(" - result 1: {}", ecs.objects.get_ref(&entity1).call1);
-    }
-    assert!(false);
+    ecs.update();
+    assert_eq!(early.get(), 2);
 }
 
-#[derive(Default, Debug)]
+#[derive(Default, Debug, Reflect)]
 struct Cell {
     pub spawned: bool,
     pub call1: u128,
@@ -141,6 +35,8 @@ impl<'a> Factory<'a, Cell> for Factory1 {
         tools.add_component("call-1");
         tools.edit().call1 = 0;
     }
+
+    fn components(&self) -> Vec<NameTag> { vec![ NameTag::from_str("call-1") ] }
 }
 
 struct Factory2;
@@ -148,6 +44,8 @@ impl<'a> Factory<'a, Cell> for Factory2 {
     fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
         tools.add_component("call-2")
     }
+
+    fn components(&self) -> Vec<NameTag> { vec![ NameTag::from_str("call-2") ] }
 }
 
 struct Factory3;
@@ -155,11 +53,15 @@ impl<'a> Factory<'a, Cell> for Factory3 {
     fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
         tools.add_component("call-3")
     }
+
+    fn components(&self) -> Vec<NameTag> { vec![ NameTag::from_str("call-3") ] }
 }
 
 
 #[derive(Default)]
-struct Call1;
+struct Call1 {
+    early: Rc<std::cell::Cell<usize>>, // times on_early_update ran
+}
 
 impl Behaviour<Cell> for Call1 {
 
@@ -174,7 +76,7 @@ impl Behaviour<Cell> for Call1 {
 
     #[allow(unused_variables)]
     fn on_early_update(&mut self, objects: &mut Objects<Cell>, system: &mut System) {
-        unsafe { TEST_EARLY += 1; }
+        self.early.set(self.early.get() + 1);
     }
 
     #[allow(unused_variables)]
@@ -198,17 +100,1748 @@ impl Behaviour<Cell> for Call2 {
     }
 }
 
-#[derive(Default)]
-struct Call3;
+impl PrefabData for Cell {
+    fn set_field(&mut self, field: &str, value: &str) -> Result<(), String> {
+        let parse = |v: &str| v.parse::<u128>().map_err(|e| e.to_string());
+        match field {
+            "spawned" => self.spawned = value.parse::<bool>().map_err(|e| e.to_string())?,
+            "call1" => self.call1 = parse(value)?,
+            "call2" => self.call2 = parse(value)?,
+            "call3" => self.call3 = parse(value)?,
+            _ => return Err(format!("unknown field '{}'", field)),
+        }
+        Ok(())
+    }
+}
+
+const PREFABS: &str = "
+# test prefabs
+[prefab-1]
+components = call-1, call-2
+call1 = 5
+spawned = true
+
+[prefab-2]
+components = call-3
+";
+
+#[test]
+fn spawn_from_prefab() {
+    let mut ecs = EcsBuilder::new(10)
+            .define_component("call-1")
+            .define_component("call-2")
+            .define_component("call-3")
+        .build_systems()
+            .define_system(Box::new(Call2))
+        .setup_factories()
+            .define_prefabs("prefabs.txt", PREFABS).unwrap()
+        .finalize();
+
+    ecs.spawn("entity-1", "prefab-1");
+    let entity1 = ecs.find("entity-1").unwrap();
+    assert_eq!(ecs.get_ref(&entity1).call1, 5);
+    assert!(ecs.get_ref(&entity1).spawned);
+    assert_eq!(ecs.entities.pool[entity1].0, 0b011);
+
+    ecs.update();
+    assert_eq!(ecs.get_ref(&entity1).call2, 1);
+}
+
+#[test]
+fn prefab_errors_point_at_line() {
+    let unknown_component = "[prefab-1]\ncomponents = call-1, call-9\n";
+    let bad_value = "[prefab-1]\n\n# comment\ncall1 = many\n";
+    let outside = "call1 = 1\n";
+
+    let build = |source: &str| EcsBuilder::new(10)
+            .define_component("call-1")
+        .build_systems::<Cell>()
+        .setup_factories()
+            .define_prefabs("prefabs.txt", source)
+            .err()
+            .unwrap();
+
+    let e = build(unknown_component);
+    assert_eq!((e.file.as_str(), e.line), ("prefabs.txt", 2));
+    assert!(e.message.contains("call-9"));
+
+    let e = build(bad_value);
+    assert_eq!(e.line, 4);
+    assert!(e.to_string().starts_with("prefabs.txt:4: field 'call1'"));
+
+    assert_eq!(build(outside).line, 1);
+}
+
+#[test]
+fn load_prefabs_from_file() {
+    let path = std::env::temp_dir().join(format!("simple_ecs_prefabs_{}.txt", std::process::id()));
+    std::fs::write(&path, PREFABS).unwrap();
+
+    let mut ecs: Ecs<Cell> = EcsBuilder::new(10)
+            .define_component("call-1")
+            .define_component("call-2")
+            .define_component("call-3")
+        .build_systems()
+        .setup_factories()
+        .finalize();
+
+    ecs.load_prefabs(&path).unwrap();
+    ecs.spawn("entity-2", "prefab-2");
+    let entity2 = ecs.find("entity-2").unwrap();
+    assert_eq!(ecs.entities.pool[entity2].0, 0b100);
+
+    std::fs::remove_file(&path).unwrap();
+    let e = ecs.load_prefabs(&path).err().unwrap();
+    assert_eq!(e.line, 0);
+}
+
+struct Override1;
+impl<'a> Factory<'a, Cell> for Override1 {
+    fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
+        assert_eq!(tools.edit().call1, 0);
+        tools.edit().call1 = 7;
+        tools.add_component("call-3");
+    }
+
+    fn components(&self) -> Vec<NameTag> { vec![ NameTag::from_str("call-3") ] }
+}
+
+#[test]
+fn extended_factories() {
+    let mut ecs = EcsBuilder::new(10)
+            .define_component("call-1")
+            .define_component("call-2")
+            .define_component("call-3")
+        .build_systems()
+        .setup_factories()
+            .extend_factory("variant-1", "type-1", Box::new(Override1))
+            .define_factory("type-1", Box::new(Factory1))
+            .define_prefabs("prefabs.txt", "[variant-2]\nextends = variant-1\ncomponents = call-2\ncall2 = 3\n").unwrap()
+        .finalize();
+
+    ecs.spawn("entity-1", "variant-2");
+    let entity1 = ecs.find("entity-1").unwrap();
+    assert_eq!(ecs.get_ref(&entity1).call1, 7);
+    assert_eq!(ecs.get_ref(&entity1).call2, 3);
+    assert_eq!(ecs.entities.pool[entity1].0, 0b111);
+
+    assert_eq!(ecs.factories().base_of("variant-2"), Some("variant-1"));
+    assert_eq!(ecs.factory_components("variant-1").unwrap(), vec!["call-1", "call-3"]);
+    assert_eq!(ecs.resolved_factories(), vec![
+        ("variant-1".to_string(), vec!["call-1".to_string(), "call-3".to_string()]),
+        ("type-1".to_string(), vec!["call-1".to_string()]),
+        ("variant-2".to_string(), vec!["call-1".to_string(), "call-2".to_string(), "call-3".to_string()]),
+    ]);
+}
+
+struct NotSpawned;
+impl<'a> Factory<'a, Cell> for NotSpawned {
+    #[allow(unused_variables)]
+    fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
+        panic!("listing must not run make_spawn");
+    }
+
+    fn components(&self) -> Vec<NameTag> { vec![ NameTag::from_str("call-2") ] }
+}
+
+#[test]
+fn listing_factories_does_not_spawn() {
+    let ecs = EcsBuilder::new(10)
+            .define_component("call-1")
+            .define_component("call-2")
+        .build_systems::<Cell>()
+        .setup_factories()
+            .extend_factory("not-spawned", "type-1", Box::new(NotSpawned))
+            .define_factory("type-1", Box::new(Factory1))
+        .finalize();
+
+    assert_eq!(ecs.factory_components("not-spawned").unwrap(), vec!["call-1", "call-2"]);
+    assert!(ecs.factory_components("missing").is_none());
+}
+
+struct Undeclared;
+impl<'a> Factory<'a, Cell> for Undeclared {
+    fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
+        tools.add_component("call-2");
+    }
+
+    fn components(&self) -> Vec<NameTag> { Vec::new() }
+}
+
+#[test]
+#[cfg(debug_assertions)]
+#[should_panic(expected = "factory 'undeclared' added components it does not declare: [\"call-2\"]")]
+fn undeclared_factory_components() {
+    let mut ecs = EcsBuilder::new(10)
+            .define_component("call-1")
+            .define_component("call-2")
+        .build_systems::<Cell>()
+        .setup_factories()
+            .extend_factory("undeclared", "type-1", Box::new(Undeclared))
+            .define_factory("type-1", Box::new(Factory1))
+        .finalize();
+
+    ecs.spawn("a", "undeclared");
+}
+
+#[test]
+fn factory_inheritance_errors() {
+    let cycle = EcsBuilder::new(10)
+        .build_systems::<Cell>()
+        .setup_factories()
+            .extend_factory("type-1", "type-3", Box::new(Factory1))
+            .extend_factory("type-2", "type-1", Box::new(Factory2))
+            .extend_factory("type-3", "type-2", Box::new(Factory3))
+        .try_finalize();
+
+    match cycle {
+        Err(FactoryError::Cycle(path)) => assert_eq!(path, vec!["type-1", "type-3", "type-2", "type-1"]),
+        _ => panic!("expected a cycle"),
+    }
+
+    let mut ecs = EcsBuilder::new(10)
+            .define_component("call-1")
+        .build_systems::<Cell>()
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+        .finalize();
+
+    let e = ecs.define_prefabs("prefabs.txt", "[prefab-1]\n\nextends = missing\n").err().unwrap();
+    assert_eq!(e.line, 3);
+    assert!(e.message.contains("unknown factory 'missing'"));
+    assert!(ecs.factories().index_of("prefab-1").is_none());
+}
+
+struct Position(u128, u128);
+
+struct ArgsFactory;
+impl<'a> Factory<'a, Cell> for ArgsFactory {
+    fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
+        tools.add_component("call-1");
+        if let Some(Position(x, y)) = tools.arg_value::<Position>() {
+            let (x, y) = (*x, *y);
+            tools.edit().call2 = x;
+            tools.edit().call3 = y;
+        }
+        if let Some(spawned) = tools.arg::<bool>("spawned") {
+            tools.edit().spawned = *spawned;
+        }
+    }
+
+    fn components(&self) -> Vec<NameTag> { vec![ NameTag::from_str("call-1") ] }
+}
+
+struct SpawnWithArgs;
+impl Behaviour<Cell> for SpawnWithArgs {
+    fn required_components(&self) -> Vec<NameTag> { vec![] }
+
+    #[allow(unused_variables)]
+    fn on_startup(&mut self, objects: &mut Objects<Cell>, system: &mut System) {
+        system.spawn_with("deferred", "args", SpawnArgs::new().with_value(Position(4, 5)));
+    }
+
+    #[allow(unused_variables)]
+    fn on_update(&mut self, target: &ObjectIndex, objects: &mut Objects<Cell>, system: &mut System) {}
+}
+
+#[test]
+fn spawn_with_args() {
+    let mut ecs = EcsBuilder::new(10)
+            .define_component("call-1")
+        .build_systems()
+            .define_system(Box::new(SpawnWithArgs))
+        .setup_factories()
+            .define_factory("args", Box::new(ArgsFactory))
+        .finalize();
 
-impl Behaviour<Cell> for Call3 {
+    let args = SpawnArgs::new()
+        .with("spawned", true)
+        .with_value(Position(1, 2));
+    let entity1 = ecs.spawn_with("entity-1", "args", args).unwrap();
+    assert!(ecs.get_ref(&entity1).spawned);
+    assert_eq!((ecs.get_ref(&entity1).call2, ecs.get_ref(&entity1).call3), (1, 2));
+
+    let entity2 = ecs.spawn("entity-2", "args").unwrap();
+    assert!(!ecs.get_ref(&entity2).spawned);
+    assert_eq!(ecs.get_ref(&entity2).call2, 0);
+
+    ecs.start();
+    let deferred = ecs.find("deferred").unwrap();
+    assert_eq!((ecs.get_ref(&deferred).call2, ecs.get_ref(&deferred).call3), (4, 5));
+}
 
+struct Burst;
+impl Behaviour<Cell> for Burst {
     fn required_components(&self) -> Vec<NameTag> { 
         vec![ NameTag::from_str("call-3") ] 
     }
 
+    #[allow(unused_variables)]
+    fn on_startup(&mut self, objects: &mut Objects<Cell>, system: &mut System) {
+        system.spawn_batch("type-3", 4);
+    }
+
     #[allow(unused_variables)]
     fn on_update(&mut self, target: &ObjectIndex, objects: &mut Objects<Cell>, system: &mut System) {
-        objects.get_mut(target).call3 += 1;
+        system.destroy(target);
+    }
+}
+
+#[test]
+fn batch_spawn_and_destroy() {
+    let mut ecs = EcsBuilder::new(10)
+            .define_component("call-1")
+            .define_component("call-2")
+            .define_component("call-3")
+        .build_systems()
+            .define_system(Box::new(Burst))
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+            .define_factory("type-2", Box::new(Factory2))
+            .define_factory("type-3", Box::new(Factory3))
+        .finalize();
+
+    let ones = ecs.spawn_batch("type-1", 3);
+    let twos = ecs.spawn_batch("type-2", 3);
+    assert_eq!(ones.len(), 3);
+    assert_eq!(ecs.entities.active.len(), 6);
+    assert!(ones.iter().all(|e| ecs.entities.pool[*e].0 == 0b001));
+
+    // only four slots remain
+    assert_eq!(ecs.spawn_batch("type-2", 5).len(), 4);
+    assert!(ecs.spawn_batch("type-2", 1).is_empty());
+
+    ecs.destroy_batch(&[ones[0], ones[2], ones[2]]);
+    assert_eq!(ecs.entities.active.len(), 8);
+    assert_eq!(ecs.entities.active, ecs.objects.active.iter().map(|a| a.0).collect::<Vec<_>>());
+    let mut free = ecs.entities.free.clone();
+    free.sort();
+    let mut expected = vec![ones[0], ones[2]];
+    expected.sort();
+    assert_eq!(free, expected);
+
+    assert_eq!(ecs.destroy_all_matching(&["call-2"]), 7);
+    assert_eq!(ecs.destroy_all_matching(&["unknown"]), 0);
+    assert_eq!(ecs.entities.active, vec![ones[1]]);
+    assert!(!twos.contains(&ones[1]));
+
+    // systems spawn bursts on startup and destroy them again during the update
+    ecs.start();
+    assert_eq!(ecs.entities.active.len(), 5);
+    ecs.update();
+    assert_eq!(ecs.entities.active, vec![ones[1]]);
+    assert_eq!(ecs.entities.free.len(), 9);
+}
+
+// small xorshift generator, keeps the random sequence tests reproducible
+struct Random(u64);
+impl Random {
+    fn next(&mut self, below: usize) -> usize {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 % below as u64) as usize
+    }
+}
+
+fn assert_consistent(ecs: &Ecs<Cell>, size: usize) {
+    let entities = &ecs.entities;
+    assert_eq!(entities.active.len() + entities.free.len(), size);
+    assert_eq!(entities.active.len(), ecs.objects.active.len());
+
+    for (dense, pointer) in entities.active.iter().enumerate() {
+        assert_eq!(entities.sparse[*pointer], dense);
+        assert_eq!(ecs.objects.active[dense].0, *pointer);
+        assert!(ecs.is_alive(pointer));
+    }
+    for pointer in &entities.free {
+        assert_eq!(entities.sparse[*pointer], pool::INACTIVE);
+        assert!(!ecs.is_alive(pointer));
     }
+    let mut slots: Vec<usize> = entities.active.iter().chain(entities.free.iter()).copied().collect();
+    slots.sort();
+    assert_eq!(slots, (0..size).collect::<Vec<_>>());
+}
+
+#[test]
+fn random_spawn_destroy_stays_consistent() {
+    let size = 64;
+
+    for seed in 1..=20u64 {
+        let mut random = Random(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15));
+        let mut ecs = EcsBuilder::new(size)
+                .define_component("call-1")
+                .define_component("call-2")
+            .build_systems()
+            .setup_factories()
+                .define_factory("type-1", Box::new(Factory1))
+                .define_factory("type-2", Box::new(Factory2))
+            .finalize();
+
+        for step in 0..500 {
+            match random.next(6) {
+                0 | 1 => { ecs.spawn(&format!("obj-{}", step), "type-1"); },
+                2 => { ecs.spawn_batch("type-2", random.next(8)); },
+                3 => {
+                    // also destroys slots that are not alive, which must be ignored
+                    let target = random.next(size);
+                    ecs.destroy(&target);
+                },
+                4 => {
+                    let targets: Vec<usize> = (0..random.next(8)).map(|_| random.next(size)).collect();
+                    ecs.destroy_batch(&targets);
+                },
+                _ => { ecs.destroy_all_matching(&["call-2"]); },
+            }
+            assert_consistent(&ecs, size);
+        }
+    }
+}
+
+#[test]
+fn destroyed_slots_are_reused_clean() {
+    let mut ecs = EcsBuilder::new(3)
+            .define_component("call-1")
+            .define_component("call-2")
+        .build_systems()
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+            .define_factory("type-2", Box::new(Factory2))
+        .finalize();
+
+    let a = ecs.spawn("a", "type-1").unwrap();
+    let b = ecs.spawn("b", "type-1").unwrap();
+    let c = ecs.spawn("c", "type-1").unwrap();
+    ecs.get_mut(&b).call2 = 9;
+
+    ecs.destroy(&a);
+    ecs.destroy(&a);
+    assert_eq!(ecs.entities.free, vec![a]);
+    assert_eq!(ecs.find("c"), Some(c));
+
+    ecs.destroy(&b);
+    let d = ecs.spawn("d", "type-2").unwrap();
+    assert_eq!(d, b);
+    assert_eq!(ecs.get_ref(&d).call2, 0);
+    assert_eq!(ecs.entities.pool[d].0, 0b10);
+    assert_eq!(ecs.find("b"), None);
+    assert_eq!(ecs.find("d"), Some(d));
+}
+
+#[test]
+fn growth_policies() {
+    let build = |growth: GrowthPolicy| EcsBuilder::new(2)
+            .with_growth(growth)
+            .define_component("call-1")
+        .build_systems::<Cell>()
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+        .finalize();
+
+    let mut fixed = build(GrowthPolicy::Fixed);
+    assert_eq!(fixed.spawn_batch("type-1", 3).len(), 2);
+    assert_eq!(fixed.spawn("full", "type-1"), None);
+    assert_eq!(fixed.capacity().capacity, 2);
+
+    let mut double = build(GrowthPolicy::Double);
+    let first = double.spawn("first", "type-1").unwrap();
+    double.get_mut(&first).call2 = 42;
+    assert_eq!(double.spawn_batch("type-1", 4).len(), 4);
+    assert_eq!(double.capacity().capacity, 8);
+    assert_eq!(double.find("first"), Some(first));
+    assert_eq!(double.get_ref(&first).call2, 42);
+
+    let mut chunked = build(GrowthPolicy::Chunked { chunk: 3, max: 6 });
+    assert_eq!(chunked.spawn_batch("type-1", 3).len(), 3);
+    assert_eq!(chunked.capacity().capacity, 5);
+    assert_eq!(chunked.spawn_batch("type-1", 10).len(), 3);
+    assert!(chunked.spawn("over", "type-1").is_none());
+
+    let spawned: Vec<usize> = chunked.entities.active.clone();
+    chunked.destroy_batch(&spawned[..4]);
+    assert_eq!(chunked.capacity(), CapacityReport {
+        capacity: 6,
+        active: 2,
+        free: 4,
+        high_water_mark: 6,
+        times_grown: 2,
+        growth: GrowthPolicy::Chunked { chunk: 3, max: 6 },
+    });
+    assert_consistent(&chunked, 6);
+}
+
+fn named_world(names: Option<NamePolicy>) -> Ecs<'static, Cell> {
+    let builder = match names {
+        Some(policy) => EcsBuilder::new(10).with_name_index(policy),
+        None => EcsBuilder::new(10),
+    };
+    builder
+            .define_component("call-1")
+        .build_systems()
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+        .finalize()
+}
+
+#[test]
+fn name_index_policies() {
+    let mut reject = named_world(Some(NamePolicy::Reject));
+    let player = reject.spawn("player", "type-1").unwrap();
+    assert_eq!(reject.spawn("player", "type-1"), None);
+    assert_eq!(reject.entities.active.len(), 1);
+    let camera = reject.spawn("camera", "type-1").unwrap();
+    assert!(!reject.rename(&camera, "player"));
+    assert!(reject.rename(&camera, "eye"));
+    assert_eq!(reject.find("camera"), None);
+    assert_eq!(reject.find("eye"), Some(camera));
+    reject.destroy(&player);
+    assert_eq!(reject.find("player"), None);
+    assert!(reject.spawn("player", "type-1").is_some());
+    // unnamed objects never collide
+    assert_eq!(reject.spawn_batch("type-1", 3).len(), 3);
+
+    let mut replace = named_world(Some(NamePolicy::Replace));
+    let old = replace.spawn("player", "type-1").unwrap();
+    let new = replace.spawn("player", "type-1").unwrap();
+    assert_eq!(replace.find_all("player"), vec![new]);
+    assert_eq!(replace.name_of(&old), Some(""));
+    replace.destroy(&old);
+    assert_eq!(replace.find("player"), Some(new));
+
+    let mut duplicates = named_world(Some(NamePolicy::AllowDuplicates));
+    let a = duplicates.spawn("enemy", "type-1").unwrap();
+    let b = duplicates.spawn("enemy", "type-1").unwrap();
+    let c = duplicates.spawn("enemy", "type-1").unwrap();
+    assert_eq!(duplicates.find_all("enemy"), vec![a, b, c]);
+    duplicates.destroy(&b);
+    assert!(duplicates.rename(&a, "boss"));
+    assert_eq!(duplicates.find_all("enemy"), vec![c]);
+    assert_eq!(duplicates.find("boss"), Some(a));
+}
+
+#[test]
+fn find_without_name_index() {
+    let mut ecs = named_world(None);
+    let a = ecs.spawn("enemy", "type-1").unwrap();
+    let b = ecs.spawn("enemy", "type-1").unwrap();
+    assert_eq!(ecs.find_all("enemy"), vec![a, b]);
+    assert!(ecs.rename(&b, "boss"));
+    assert_eq!(ecs.find("boss"), Some(b));
+    ecs.destroy(&a);
+    assert!(!ecs.rename(&a, "ghost"));
+    assert!(ecs.find_all("enemy").is_empty());
+}
+
+#[test]
+fn long_names_round_trip() {
+    let mut ecs = EcsBuilder::new(10)
+            .with_name_index(NamePolicy::Reject)
+            .define_component("component_with_a_long_name")
+        .build_systems::<Cell>()
+        .setup_factories()
+            .define_prefabs("prefabs.txt", "[projectile_small_a]\ncomponents = component_with_a_long_name\n").unwrap()
+        .finalize();
+
+    let a = ecs.spawn("projectile_small_a", "projectile_small_a").unwrap();
+    let b = ecs.spawn("projectile_small_b", "projectile_small_a").unwrap();
+    assert_eq!(ecs.find("projectile_small_a"), Some(a));
+    assert_eq!(ecs.find("projectile_small_b"), Some(b));
+    assert_eq!(ecs.find("projectile_small_"), None);
+    assert_eq!(ecs.entities.pool[b].0, 0b1);
+
+    let name = "ünïcödé names are kept whole";
+    assert!(ecs.rename(&b, name));
+    assert_eq!(ecs.name_of(&b), Some(name));
+    assert_eq!(NameTag::from_str(name).to_string(), name);
+
+    let symbol = ecs.symbols().get("component_with_a_long_name").unwrap();
+    assert_eq!(ecs.components().get_symbol(symbol).unwrap().index(), &0);
+    assert_eq!(ecs.symbols().resolve(symbol), "component_with_a_long_name");
+    assert_eq!(ecs.symbols().get(""), Some(Symbol::EMPTY));
+
+    // object names are freed with the last object using them
+    let interned = ecs.symbols().len();
+    for i in 0..20 {
+        let bullet = ecs.spawn(&format!("bullet-{}", i), "projectile_small_a").unwrap();
+        ecs.destroy(&bullet);
+    }
+    assert_eq!(ecs.symbols().len(), interned);
+    assert!(ecs.rename(&b, "renamed"));
+    assert!(ecs.symbols().get(name).is_none());
+    assert_eq!(ecs.symbols().resolve(symbol), "component_with_a_long_name");
+}
+
+components! {
+    Walker = "walker";
+    Flyer = "flyer";
+    Undefined = "undefined";
+}
+
+struct TypedFactory;
+impl<'a> Factory<'a, Cell> for TypedFactory {
+    fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
+        tools.add::<Walker>();
+        tools.add::<Flyer>();
+        tools.remove::<Flyer>();
+        tools.edit().spawned = tools.has::<Walker>();
+    }
+
+    fn components(&self) -> Vec<NameTag> { vec![ NameTag::of::<Walker>(), NameTag::of::<Flyer>() ] }
+}
+
+struct Walk;
+impl Behaviour<Cell> for Walk {
+    fn required_components(&self) -> Vec<NameTag> { 
+        vec![ NameTag::of::<Walker>() ] 
+    }
+
+    #[allow(unused_variables)]
+    fn on_update(&mut self, target: &ObjectIndex, objects: &mut Objects<Cell>, system: &mut System) {
+        objects.get_mut(target).call1 += 1;
+    }
+}
+
+struct UndefinedFactory;
+impl<'a> Factory<'a, Cell> for UndefinedFactory {
+    fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
+        tools.add::<Undefined>();
+    }
+
+    fn components(&self) -> Vec<NameTag> { vec![ NameTag::of::<Undefined>() ] }
+}
+
+struct WalkUndefined;
+impl Behaviour<Cell> for WalkUndefined {
+    fn required_components(&self) -> Vec<NameTag> { 
+        vec![ NameTag::of::<Walker>(), NameTag::of::<Undefined>() ] 
+    }
+
+    #[allow(unused_variables)]
+    fn on_update(&mut self, target: &ObjectIndex, objects: &mut Objects<Cell>, system: &mut System) {}
+}
+
+#[test]
+fn typed_component_keys() {
+    let mut ecs = EcsBuilder::new(10)
+            .define::<Walker>()
+            .define::<Flyer>()
+        .build_systems()
+            .define_system(Box::new(Walk))
+        .setup_factories()
+            .define_factory("walker", Box::new(TypedFactory))
+        .finalize();
+
+    let walker = ecs.spawn("walker", "walker").unwrap();
+    assert!(ecs.has::<Walker>(&walker));
+    assert!(!ecs.has::<Flyer>(&walker));
+    assert!(!ecs.has::<Undefined>(&walker));
+    assert!(ecs.get_ref(&walker).spawned);
+    assert_eq!(ecs.components().of::<Flyer>().unwrap().name().as_str(), "flyer");
+
+    ecs.update();
+    assert_eq!(ecs.get_ref(&walker).call1, 1);
+
+    // undefined keys are reported by finalize instead of matching every entity
+    let undefined_system = EcsBuilder::new(10)
+            .define::<Walker>()
+        .build_systems::<Cell>()
+            .define_system(Box::new(Walk))
+            .define_system(Box::new(WalkUndefined))
+        .setup_factories()
+        .try_finalize();
+    assert_eq!(undefined_system.err(), Some(FactoryError::UnknownSystemComponent { system: 1, component: "undefined".to_string() }));
+
+    let undefined_factory = EcsBuilder::new(10)
+            .define::<Walker>()
+        .build_systems::<Cell>()
+        .setup_factories()
+            .define_factory("undefined", Box::new(UndefinedFactory))
+        .try_finalize();
+    assert_eq!(undefined_factory.err(), Some(FactoryError::UnknownComponent { factory: "undefined".to_string(), component: "undefined".to_string() }));
+}
+
+struct LooseFactory;
+impl<'a> Factory<'a, Cell> for LooseFactory {
+    fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
+        tools.add::<Walker>();
+        tools.add::<Undefined>();
+        tools.edit().spawned = tools.has::<Undefined>();
+    }
+
+    fn components(&self) -> Vec<NameTag> { vec![ NameTag::of::<Walker>() ] }
+}
+
+// undeclared and undefined keys are ignored like string names, debug builds assert
+#[test]
+#[cfg_attr(debug_assertions, should_panic(expected = "component 'undefined' was not defined in the EcsBuilder"))]
+fn typed_build_tools_ignore_undefined() {
+    let mut ecs = EcsBuilder::new(10)
+            .define::<Walker>()
+        .build_systems::<Cell>()
+        .setup_factories()
+            .define_factory("loose", Box::new(LooseFactory))
+        .finalize();
+
+    let loose = ecs.spawn("loose", "loose").unwrap();
+    assert!(ecs.has::<Walker>(&loose));
+    assert!(!ecs.get_ref(&loose).spawned);
+}
+
+#[derive(Component)]
+struct MoveSpeed;
+
+#[derive(Component)]
+#[component(name = "frozen")]
+struct Frozen;
+
+struct Mover;
+
+#[system(reads(MoveSpeed), writes(Walker), without(Frozen))]
+impl Behaviour<Cell> for Mover {
+    #[allow(unused_variables)]
+    fn on_update(&mut self, target: &ObjectIndex, objects: &mut Objects<Cell>, system: &mut System) {
+        objects.get_mut(target).call2 += 1;
+    }
+}
+
+struct FrozenWalker;
+impl<'a> Factory<'a, Cell> for FrozenWalker {
+    fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
+        tools.add::<MoveSpeed>();
+        tools.add::<Walker>();
+        if *tools.arg::<bool>("frozen").unwrap_or(&false) {
+            tools.add::<Frozen>();
+        }
+    }
+
+    fn components(&self) -> Vec<NameTag> { vec![ NameTag::of::<MoveSpeed>(), NameTag::of::<Walker>(), NameTag::of::<Frozen>() ] }
+}
+
+#[test]
+fn derived_components_and_systems() {
+    assert_eq!(MoveSpeed::NAME, "move_speed");
+    assert_eq!(Frozen::NAME, "frozen");
+
+    let mut ecs = EcsBuilder::new(10)
+            .define::<MoveSpeed>()
+            .define::<Walker>()
+            .define::<Frozen>()
+        .build_systems()
+            .define_system(Box::new(Mover))
+        .setup_factories()
+            .define_factory("walker", Box::new(FrozenWalker))
+        .finalize();
+
+    assert_eq!(ecs.systems[0].access(), &Access {
+        reads: vec![NameTag::of::<MoveSpeed>()],
+        writes: vec![NameTag::of::<Walker>()],
+        without: vec![NameTag::of::<Frozen>()],
+        ..Access::default()
+    });
+
+    let moving = ecs.spawn("moving", "walker").unwrap();
+    let frozen = ecs.spawn_with("frozen", "walker", SpawnArgs::new().with("frozen", true)).unwrap();
+    let still = ecs.spawn("still", "").unwrap();
+    ecs.update();
+
+    assert_eq!(ecs.get_ref(&moving).call2, 1);
+    assert_eq!(ecs.get_ref(&frozen).call2, 0);
+    assert_eq!(ecs.get_ref(&still).call2, 0);
+}
+
+#[test]
+fn closure_systems() {
+    let mut ecs = EcsBuilder::new(10)
+            .define_component("call-1")
+            .define_component("call-2")
+        .build_systems()
+            .define_system_fn(&["call-1"], |target, objects: &mut Objects<Cell>, _| {
+                objects.get_mut(target).call1 += 10;
+            })
+            .define_startup_fn(|_, system| system.spawn("from-startup", "type-2"))
+            .define_early_update_fn(|objects, system| {
+                if let Some(target) = objects.find("from-startup") {
+                    objects.get_mut(&target).call3 += 1;
+                    if objects.get_ref(&target).call3 == 2 { system.destroy(&target); }
+                }
+            })
+            .define_system(Box::new(FnBehaviour::new(&["call-2"])
+                .on_update(|target, objects: &mut Objects<Cell>, _| objects.get_mut(target).call2 += 1)))
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+            .define_factory("type-2", Box::new(Factory2))
+        .finalize();
+
+    let one = ecs.spawn("one", "type-1").unwrap();
+    assert!(ecs.systems[0].updates_entities);
+    assert!(!ecs.systems[1].updates_entities);
+
+    ecs.start();
+    let started = ecs.find("from-startup").unwrap();
+    assert_eq!(ecs.get_ref(&started).call3, 1);
+
+    ecs.update();
+    assert_eq!(ecs.get_ref(&one).call1, 10);
+    assert_eq!(ecs.get_ref(&started).call2, 1);
+    assert_eq!(ecs.find("from-startup"), None);
+}
+
+#[cfg(feature = "scripting")]
+const SCRIPT: &str = "
+# counts up and hands over to a fresh object
+components \"call-1\"
+without \"call-3\"
+
+on_startup {
+    spawn(\"scripted\", \"type-1\")
+}
+
+on_update {
+    let step = max(2, 1) * 0.5
+    call1 += floor(step)
+    if call1 >= 6 and not spawned {
+        spawned = true
+        spawn(\"replacement\", \"type-1\")
+        destroy()
+    } else if call1 == 2 {
+        call2 = -1 * -7 % 4
+    }
+}
+";
+
+#[cfg(feature = "scripting")]
+#[test]
+fn script_behaviours() {
+    let mut ecs = EcsBuilder::new(10)
+            .define_component("call-1")
+            .define_component("call-3")
+        .build_systems()
+            .define_script("counter.script", SCRIPT).unwrap()
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+            .define_factory("type-3", Box::new(Factory3))
+        .finalize();
+
+    let skipped = ecs.spawn("skipped", "type-1").unwrap();
+    ecs.get_mut(&skipped).call3 = 1;
+    ecs.entities.pool[skipped].set_bit(1, true);
+
+    ecs.start();
+    let scripted = ecs.find("scripted").unwrap();
+    ecs.update();
+    assert_eq!(ecs.get_ref(&scripted).call1, 1);
+    ecs.update();
+    assert_eq!(ecs.get_ref(&scripted).call1, 2);
+    assert_eq!(ecs.get_ref(&scripted).call2, 3);
+    ecs.update();
+    ecs.update();
+    ecs.update();
+    ecs.update();
+    assert_eq!(ecs.find("scripted"), None);
+    assert!(ecs.find("replacement").is_some());
+    assert_eq!(ecs.get_ref(&skipped).call1, 0);
+}
+
+#[cfg(feature = "scripting")]
+#[test]
+fn script_errors_point_at_line() {
+    let refs = {
+        let ecs: Ecs<Cell> = EcsBuilder::new(1)
+                .define_component("call-1")
+            .build_systems()
+            .setup_factories()
+            .finalize();
+        ecs.component_refs
+    };
+    let error = |source: &str| ScriptBehaviour::compile::<Cell>("test.script", source, &refs).err().unwrap();
+
+    assert_eq!(error("components \"call-2\"").message, "unknown component 'call-2'");
+    assert_eq!(error("on_update {\n  speed = 1\n}").message, "unknown field 'speed'");
+    assert_eq!(error("on_startup {\n\n  call1 = 1\n}").line, 3);
+    assert_eq!(error("on_early_update { destroy() }").message, "destroy() is only available in on_update");
+    assert_eq!(error("on_update { spawn(1) }").message, "spawn() takes 2 arguments, found 1");
+    assert_eq!(error("on_update { call1 = 1 ").message, "missing '}'");
+    assert_eq!(error("on_update {}\non_update {}").to_string(), "test.script:2: 'on_update' is defined twice");
+
+    // runtime errors disable the system instead of panicking
+    let mut ecs: Ecs<Cell> = EcsBuilder::new(4)
+            .define_component("call-1")
+        .build_systems()
+            .define_script("overflow.script", "components \"call-1\"\non_update {\n  call1 += 1\n  let x = abs(-9223372036854775807 - 1)\n  let y = x / -1\n}").unwrap()
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+        .finalize();
+
+    let a = ecs.spawn("a", "type-1").unwrap();
+    ecs.spawn("b", "type-1");
+    ecs.update();
+    ecs.update();
+    assert!(!ecs.system_enabled(0));
+    assert_eq!(ecs.system_error(0), Some("overflow.script:5: integer overflow in '/'"));
+    assert_eq!(ecs.get_ref(&a).call1, 1);
+
+    ecs.set_system_enabled(0, true);
+    assert_eq!(ecs.system_error(0), None);
+}
+
+#[derive(Default, Debug, Reflect)]
+struct Stats(u8, f32);
+
+#[derive(Default, Debug, Reflect)]
+struct Unit {
+    name: String,
+    stats: Stats,
+    #[reflect(skip)]
+    _cache: Vec<u8>,
+}
+
+#[test]
+fn reflect_by_path() {
+    let mut unit = Unit::default();
+    let fields: Vec<(&str, &str)> = unit.fields().iter().map(|f| (f.name, f.type_name)).collect();
+    assert_eq!(fields, vec![("name", "String"), ("stats", unit.stats.type_name())]);
+    assert_eq!(unit.stats.fields()[1], FieldInfo { name: "1", type_name: "f32" });
+
+    unit.set_path("name", Value::Str("archer".to_string())).unwrap();
+    unit.set_path("stats.0", Value::Int(200)).unwrap();
+    unit.set_path("stats.1", Value::Int(2)).unwrap();
+    assert_eq!(unit.get_path("stats.0"), Some(Value::Int(200)));
+    assert_eq!(unit.get_path("stats"), None);
+    assert_eq!(unit.flatten(), vec![
+        ("name".to_string(), Value::Str("archer".to_string())),
+        ("stats.0".to_string(), Value::Int(200)),
+        ("stats.1".to_string(), Value::Float(2.0)),
+    ]);
+
+    assert_eq!(unit.set_path("stats.2", Value::Int(1)), Err(ReflectError::UnknownField("stats.2".to_string())));
+    assert_eq!(unit.set_path("_cache", Value::Int(1)), Err(ReflectError::UnknownField("_cache".to_string())));
+    assert_eq!(
+        unit.set_path("stats.0", Value::Int(300)).unwrap_err().to_string(),
+        "value 300 does not fit field 'stats.0' of type u8"
+    );
+    assert_eq!(
+        unit.set_path("stats", Value::Bool(true)).unwrap_err().to_string(),
+        "field 'stats' expects a struct, found bool"
+    );
+    assert_eq!(unit.stats.0, 200);
+}
+
+struct Tracker;
+impl Behaviour<Cell> for Tracker {
+    fn required_components(&self) -> Vec<NameTag> { Vec::new() }
+
+    fn access(&self) -> Access {
+        Access {
+            changed: vec![ NameTag::from_str("call-1") ],
+            removed: vec![ NameTag::from_str("call-2") ],
+            ..Access::default()
+        }
+    }
+
+    fn on_early_update(&mut self, objects: &mut Objects<Cell>, system: &mut System) {
+        for target in system.removed("call-2") {
+            objects.get_mut(&target).call2 += 100;
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn on_update(&mut self, target: &ObjectIndex, objects: &mut Objects<Cell>, system: &mut System) {
+        objects.get_mut(target).call3 += 1;
+    }
+}
+
+struct Adds;
+impl Behaviour<Cell> for Adds {
+    fn required_components(&self) -> Vec<NameTag> { Vec::new() }
+
+    fn access(&self) -> Access {
+        Access { 
+            added: vec![ NameTag::from_str("call-2") ], 
+            writes: vec![ NameTag::from_str("call-2") ], 
+            ..Access::default() 
+        }
+    }
+
+    #[allow(unused_variables)]
+    fn on_update(&mut self, target: &ObjectIndex, objects: &mut Objects<Cell>, system: &mut System) {
+        objects.get_mut(target).call2 += 1;
+    }
+}
+
+#[test]
+fn change_detection() {
+    let mut ecs = EcsBuilder::new(10)
+            .define_component("call-1")
+            .define_component("call-2")
+        .build_systems()
+            .define_system(Box::new(Tracker))
+            .define_system(Box::new(Adds))
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+        .finalize();
+
+    let a = ecs.spawn("a", "type-1").unwrap();
+    let b = ecs.spawn("b", "type-1").unwrap();
+    ecs.start();
+
+    // spawning counts as a change, a system doesn't see its own changes
+    ecs.update();
+    ecs.update();
+    assert_eq!((ecs.get_ref(&a).call3, ecs.get_ref(&b).call3), (1, 1));
+
+    let before = ecs.tick();
+    ecs.get_mut(&a);
+    assert_eq!(ecs.changed_since(before), vec![a]);
+    ecs.update();
+    assert_eq!((ecs.get_ref(&a).call3, ecs.get_ref(&b).call3), (2, 1));
+
+    // Adds only writes call-2, so its borrow is no change to call-1 for Tracker
+    assert!(ecs.add_component(&b, "call-2"));
+    assert!(!ecs.add_component(&b, "call-2"));
+    assert!(!ecs.add_component(&b, "call-9"));
+    ecs.update();
+    assert_eq!((ecs.get_ref(&b).call2, ecs.get_ref(&b).call3), (1, 1));
+    ecs.update();
+    assert_eq!((ecs.get_ref(&b).call2, ecs.get_ref(&b).call3), (1, 1));
+    assert!(ecs.objects.changed_since(b, 1, before) && !ecs.objects.changed_since(b, 0, before));
+
+    assert!(ecs.remove_component(&b, "call-2"));
+    ecs.update();
+    ecs.update();
+    assert_eq!(ecs.get_ref(&b).call2, 101);
+    assert!(ecs.entities.removed.is_empty());
+
+    // destroying reports the components the object had
+    ecs.add_component(&a, "call-2");
+    ecs.update();
+    ecs.destroy(&a);
+    ecs.update();
+    assert!(!ecs.is_alive(&a));
+    assert_eq!(ecs.objects.pool[a].call2, 101);
+
+    // removals are not kept for a disabled system, it doesn't see them once enabled
+    let d = ecs.spawn("d", "type-1").unwrap();
+    ecs.add_component(&d, "call-2");
+    ecs.update();
+    ecs.set_system_enabled(0, false);
+    ecs.remove_component(&d, "call-2");
+    for _ in 0..20 {
+        let e = ecs.spawn("", "type-1").unwrap();
+        ecs.destroy(&e);
+        ecs.update();
+    }
+    assert!(ecs.entities.removed.is_empty());
+    ecs.set_system_enabled(0, true);
+    ecs.update();
+    assert_eq!(ecs.get_ref(&d).call2, 1);
+
+    // nor do they pile up while the world isn't updated
+    for _ in 0..MAX_REMOVED + 10 {
+        let e = ecs.spawn("", "type-1").unwrap();
+        ecs.destroy(&e);
+    }
+    assert!(ecs.entities.removed.len() <= MAX_REMOVED);
+}
+
+fn replicated_world() -> Ecs<'static, Cell> {
+    EcsBuilder::new(10)
+            .define_component("call-1")
+            .define_component("call-2")
+            .define_component("call-3")
+        .build_systems()
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+            .define_factory("type-3", Box::new(Factory3))
+        .finalize()
+}
+
+#[test]
+fn replicate_over_loopback() {
+    let replication = Replication::new()
+        .component("call-1", &["call1"]).unwrap()
+        .component("call-2", &["call2", "spawned"]).unwrap();
+
+    let mut server = replicated_world();
+    let mut client = replicated_world();
+    let mut replicator = ReplicationServer::new(replication.clone());
+    let (to_client, to_server) = Loopback::pair();
+    replicator.add_client(Box::new(to_server));
+    let mut mirror = ReplicationClient::new(replication.clone(), Box::new(to_client));
+
+    let a = server.spawn("a", "type-1").unwrap();
+    let b = server.spawn("b", "type-1").unwrap();
+    let hidden = server.spawn("hidden", "type-3").unwrap();
+    server.get_mut(&a).call1 = 7;
+    server.get_mut(&hidden).call3 = 3;
+
+    replicator.replicate(&server);
+    assert_eq!(mirror.apply(&mut client), Ok(1));
+    let local_a = mirror.local(a).unwrap();
+    assert_eq!(client.name_of(&local_a), Some("a"));
+    assert_eq!(client.get_ref(&local_a).call1, 7);
+    assert_eq!(mirror.local(hidden), None);
+    assert_eq!(client.find("hidden"), None);
+
+    // nothing changed, nothing sent
+    server.update();
+    replicator.replicate(&server);
+    assert_eq!(mirror.apply(&mut client), Ok(0));
+
+    server.get_mut(&a).call1 = 8;
+    server.get_mut(&b);
+    server.add_component(&b, "call-2");
+    server.get_mut(&b).call2 = 300;
+    replicator.replicate(&server);
+    assert_eq!(mirror.apply(&mut client), Ok(1));
+    let local_b = mirror.local(b).unwrap();
+    assert_eq!(client.get_ref(&local_a).call1, 8);
+    assert_eq!(client.get_ref(&local_b).call2, 300);
+    assert!(client.has_component(&local_b, "call-2"));
+
+    // a late client gets everything at once
+    let (late_to_client, late_to_server) = Loopback::pair();
+    replicator.add_client(Box::new(late_to_server));
+    let mut late = replicated_world();
+    let mut late_mirror = ReplicationClient::new(replication, Box::new(late_to_client));
+
+    // destroyed objects and reused slots are told apart
+    server.remove_component(&b, "call-2");
+    server.destroy(&a);
+    let reused = server.spawn("c", "type-1").unwrap();
+    assert_eq!(reused, a);
+    server.update();
+    replicator.replicate(&server);
+    assert_eq!(mirror.apply(&mut client), Ok(1));
+    assert_eq!(late_mirror.apply(&mut late), Ok(1));
+
+    assert!(!client.has_component(&local_b, "call-2"));
+    let local_c = mirror.local(reused).unwrap();
+    assert_eq!(client.name_of(&local_c), Some("c"));
+    assert_eq!(client.find("a"), None);
+    assert_eq!(late.find_all("b").len(), 1);
+    assert_eq!(late.find_all("c").len(), 1);
+    assert_eq!(late.find("a"), None);
+
+    let (mut sender, receiver) = Loopback::pair();
+    let mut broken = ReplicationClient::new(Replication::new(), Box::new(receiver));
+    sender.send(vec![2, 40, 0, 0]);
+    assert_eq!(broken.apply(&mut client), Err(ReplicationError::UnknownEntity(40)));
+    sender.send(vec![1, 1]);
+    assert!(matches!(broken.apply(&mut client), Err(ReplicationError::Decode(_))));
+    // a string length of u64::MAX
+    sender.send(vec![1, 1, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01]);
+    assert!(matches!(broken.apply(&mut client), Err(ReplicationError::Decode(_))));
+    // a packet that fails to decode is not applied at all
+    let active = client.capacity().active;
+    sender.send(vec![1, 1, 1, b'd', 0, 0, 9]);
+    assert!(matches!(broken.apply(&mut client), Err(ReplicationError::Decode(_))));
+    assert_eq!(broken.local(1), None);
+    assert_eq!(client.capacity().active, active);
+    assert_eq!(client.find("d"), None);
+
+    let mut full = Replication::new();
+    for i in 0..32 {
+        full = full.component(&format!("component-{}", i), &[]).unwrap();
+    }
+    assert_eq!(full.component("component-32", &[]).err(), Some(ReplicationError::TooManyComponents));
+}
+
+#[test]
+fn replicate_taken_names() {
+    let replication = Replication::new().component("call-1", &["call1"]).unwrap();
+    let mut server = replicated_world();
+    let mut client = EcsBuilder::new(10)
+            .with_name_index(NamePolicy::Reject)
+            .define_component("call-1")
+        .build_systems::<Cell>()
+        .setup_factories()
+        .finalize();
+    let mut replicator = ReplicationServer::new(replication.clone());
+    let (to_client, to_server) = Loopback::pair();
+    replicator.add_client(Box::new(to_server));
+    let mut mirror = ReplicationClient::new(replication, Box::new(to_client));
+
+    let taken = client.spawn("a", "").unwrap();
+    let a = server.spawn("a", "type-1").unwrap();
+    let b = server.spawn("b", "type-1").unwrap();
+    server.get_mut(&b).call1 = 4;
+    replicator.replicate(&server);
+    assert_eq!(mirror.apply(&mut client), Err(ReplicationError::NameTaken { entity: a as u64, name: "a".to_string() }));
+
+    // the entity is still mirrored, and so is the rest of the packet
+    let local_a = mirror.local(a).unwrap();
+    assert_ne!(local_a, taken);
+    assert_eq!(client.name_of(&local_a), Some(""));
+    assert_eq!(client.get_ref(&mirror.local(b).unwrap()).call1, 4);
+
+    // a reused server slot may take the name of the entity it replaces
+    server.destroy(&b);
+    assert_eq!(server.spawn("b", "type-1"), Some(b));
+    server.update();
+    replicator.replicate(&server);
+    assert_eq!(mirror.apply(&mut client), Ok(1));
+    assert_eq!(client.find_all("b"), vec![mirror.local(b).unwrap()]);
+}
+
+#[test]
+fn profiler_reports() {
+    let mut ecs = EcsBuilder::new(10)
+            .with_profiler(4)
+            .define_component("call-1")
+            .define_component("call-2")
+        .build_systems()
+            .define_system(Box::new(Call1::default()))
+            .define_system(Box::new(Call2))
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+            .define_factory("type-2", Box::new(Factory2))
+        .finalize();
+
+    ecs.spawn("one", "type-1");
+    ecs.spawn_batch("type-2", 3);
+    ecs.start();
+    for _ in 0..6 { ecs.update(); }
+
+    let report = ecs.profile_report().unwrap();
+    assert_eq!((report.frames, report.window), (6, 4));
+    assert_eq!(report.systems.len(), 2);
+    assert_eq!(report.systems[0].label, "(call-1)");
+    // Call1 spawned one more type-2 at startup
+    assert_eq!(report.systems[0].matched, 1);
+    assert_eq!(report.systems[1].matched, 4);
+    assert_eq!(report.systems[1].average_matched, 4.0);
+    assert!(report.frame.max >= report.frame.p50 && report.frame.p99 >= report.frame.p50);
+    assert!(report.frame.average >= report.systems[1].update.average);
+    assert!(report.to_string().contains("on_update"));
+
+    let trace = ecs.chrome_trace().unwrap();
+    assert!(trace.starts_with("{\"traceEvents\":[") && trace.ends_with("]}"));
+    // frame and two phases per system for each of the 4 frames kept
+    assert_eq!(trace.matches("\"ph\":\"X\"").count(), 4 * (1 + 2 * 2));
+    assert!(trace.contains("\"name\":\"system 1 (call-2)\""));
+
+    // labels are escaped, control characters included
+    let mut odd: Ecs<Cell> = EcsBuilder::new(1)
+            .with_profiler(1)
+            .define_component("tab\t\"quoted\"\u{1}")
+        .build_systems()
+            .define_system_fn(&["tab\t\"quoted\"\u{1}"], |_, _, _| {})
+        .setup_factories()
+        .finalize();
+    odd.update();
+    let trace = odd.chrome_trace().unwrap();
+    assert!(trace.contains("\"name\":\"system 0 (tab\\t\\\"quoted\\\"\\u0001)\""));
+    assert!(!trace.chars().any(|c| c.is_control()));
+
+    let plain: Ecs<Cell> = EcsBuilder::new(1).build_systems().setup_factories().finalize();
+    assert!(plain.profile_report().is_none());
+    assert!(plain.write_chrome_trace(std::env::temp_dir().join("unused.json")).is_err());
+}
+
+#[test]
+fn dump_world() {
+    let mut ecs = EcsBuilder::new(4)
+            .define_component("call-1")
+            .define_component("call-2")
+        .build_systems()
+            .define_system(Box::new(Call1::default()))
+            .define_system(Box::new(Call2))
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+            .define_factory("type-2", Box::new(Factory2))
+        .finalize();
+
+    let one = ecs.spawn("one", "type-1").unwrap();
+    let two = ecs.spawn("two \"quoted\"", "type-2").unwrap();
+    ecs.add_component(&two, "call-1");
+    ecs.get_mut(&two).call2 = 7;
+    let gone = ecs.spawn("gone", "type-2").unwrap();
+    ecs.destroy(&gone);
+
+    let dump = ecs.dump();
+    assert_eq!(dump.entities.len(), 2);
+    assert_eq!((dump.capacity.active, dump.capacity.free), (2, 2));
+    assert_eq!(dump.next_free, Some(gone));
+    let entry = dump.entities.iter().find(|e| e.index == two).unwrap();
+    assert_eq!(entry.components, vec!["call-1", "call-2"]);
+    assert_eq!(entry.systems, vec![0, 1]);
+    assert!(entry.data.contains("call2: 7"));
+    let mut both = vec![one, two];
+    both.sort_unstable();
+    assert_eq!(dump.systems[0].matched, both);
+    assert_eq!(dump.systems[1].label, "(call-2)");
+    assert_eq!(dump.systems[1].matched, vec![two]);
+
+    let text = dump.to_string();
+    assert!(text.contains("entities: 2") && text.contains("'one'"));
+    let json = dump.to_json();
+    assert!(json.starts_with("{\"tick\":") && json.ends_with("]}"));
+    assert!(json.contains("\"name\":\"two \\\"quoted\\\"\""));
+    assert!(json.contains("\"components\":[\"call-1\"]"));
+
+    let named = ecs.dump_matching(DumpFilter::Name("one".to_string()));
+    assert_eq!(named.entities.len(), 1);
+    assert_eq!(named.systems[0].matched, vec![one]);
+    assert!(named.systems[1].matched.is_empty());
+    let with = ecs.dump_matching(DumpFilter::Component("call-2".to_string()));
+    assert_eq!(with.entities.iter().map(|e| e.index).collect::<Vec<_>>(), vec![two]);
+    assert!(ecs.dump_matching(DumpFilter::Component("unknown".to_string())).entities.is_empty());
+}
+
+#[test]
+fn disabled_systems_are_skipped() {
+    let mut ecs = EcsBuilder::new(4)
+            .define_component("call-2")
+        .build_systems()
+            .define_system(Box::new(Call2))
+        .setup_factories()
+            .define_factory("type-2", Box::new(Factory2))
+        .finalize();
+
+    let target = ecs.spawn("", "type-2").unwrap();
+    ecs.start();
+    assert!(ecs.set_system_enabled(0, false));
+    assert!(!ecs.system_enabled(0));
+    ecs.update();
+    assert_eq!(ecs.get_ref(&target).call2, 0);
+    ecs.set_system_enabled(0, true);
+    ecs.update();
+    assert_eq!(ecs.get_ref(&target).call2, 1);
+    assert!(!ecs.set_system_enabled(1, false));
+}
+
+#[cfg(feature = "inspector")]
+#[test]
+fn inspector_over_tcp() {
+    use std::io::{ BufRead, BufReader, Write };
+    use std::net::TcpStream;
+
+    let mut ecs = EcsBuilder::new(4)
+            .define_component("call-1")
+            .define_component("call-2")
+        .build_systems()
+            .define_system(Box::new(Call2))
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+            .define_factory("type-2", Box::new(Factory2))
+        .finalize();
+    let player = ecs.spawn("player", "type-2").unwrap();
+
+    assert!(Inspector::<Cell>::bind_tcp("0.0.0.0:0").is_err());
+    let mut inspector = Inspector::bind_tcp("127.0.0.1:0").unwrap()
+        .field("call2", |cell: &Cell| cell.call2.to_string(), |cell, value| {
+            cell.call2 = value.parse().map_err(|e| format!("{}", e))?;
+            Ok(())
+        });
+
+    let stream = TcpStream::connect(inspector.local_addr().unwrap()).unwrap();
+    stream.set_read_timeout(Some(std::time::Duration::from_secs(5))).unwrap();
+    let mut reader = BufReader::new(stream.try_clone().unwrap());
+    let mut writer = stream;
+
+    let mut ask = |ecs: &mut Ecs<Cell>, command: &str| -> Vec<String> {
+        writeln!(writer, "{}", command).unwrap();
+        // the client may not be accepted on the first poll
+        while inspector.poll(ecs) == 0 {}
+        let mut lines = Vec::new();
+        loop {
+            let mut line = String::new();
+            reader.read_line(&mut line).unwrap();
+            let line = line.trim_end().to_string();
+            let done = line == "ok" || line.starts_with("error");
+            lines.push(line);
+            if done { return lines; }
+        }
+    };
+
+    assert_eq!(ask(&mut ecs, "list"), vec![format!("{} 'player' [call-2]", player), "ok".to_string()]);
+    assert_eq!(ask(&mut ecs, "set player call2 41"), vec!["ok"]);
+    ecs.update();
+    assert_eq!(ask(&mut ecs, &format!("get {} call2", player)), vec!["42", "ok"]);
+    assert!(ask(&mut ecs, "show player").iter().any(|l| l.contains("call2: 42")));
+    assert_eq!(ask(&mut ecs, "set player call2 x")[0], "error invalid digit found in string");
+    assert_eq!(ask(&mut ecs, "set  player  call2  40"), vec!["ok"]);
+    assert_eq!(ask(&mut ecs, "set player\t\tcall2 \t 40"), vec!["ok"]);
+    assert_eq!(ecs.get_ref(&player).call2, 40);
+    assert_eq!(ask(&mut ecs, "set player  call2  ")[0], "error missing value");
+    ecs.get_mut(&player).call2 = 42;
+
+    assert_eq!(ask(&mut ecs, "disable 0"), vec!["ok"]);
+    ecs.update();
+    assert_eq!(ask(&mut ecs, "systems"), vec!["0 off (call-2)", "ok"]);
+    assert_eq!(ecs.get_ref(&player).call2, 42);
+    assert_eq!(ask(&mut ecs, "enable 3"), vec!["error no system 3"]);
+
+    let spawned = ask(&mut ecs, "spawn type-1 enemy");
+    assert_eq!(spawned.len(), 2);
+    assert!(ecs.find("enemy").is_some());
+    assert_eq!(ask(&mut ecs, "list with call-1").len(), 2);
+    assert_eq!(ask(&mut ecs, "spawn type-9")[0], "error unknown factory 'type-9'");
+    assert_eq!(ask(&mut ecs, "factories"), vec!["type-1 [call-1]", "type-2 [call-2]", "ok"]);
+    assert_eq!(ask(&mut ecs, "destroy enemy"), vec!["ok"]);
+    assert!(ecs.find("enemy").is_none());
+    assert!(ask(&mut ecs, "dump")[0].starts_with("{\"tick\":"));
+    assert_eq!(ask(&mut ecs, "jump")[0], "error unknown command 'jump', try help");
+    assert_eq!(inspector.clients(), 1);
+}
+
+fn recorded_world() -> Ecs<'static, Cell> {
+    EcsBuilder::new(4)
+            .with_growth(GrowthPolicy::Double)
+            .define_component("call-1")
+            .define_component("call-2")
+        .build_systems()
+            .define_system(Box::new(Call1::default()))
+            .define_system(Box::new(Call2))
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+            .define_factory("type-2", Box::new(Factory2))
+        .finalize()
+}
+
+#[test]
+fn record_and_replay() {
+    let mut ecs = recorded_world();
+    let mut recorder = Recorder::new(&ecs);
+    recorder.execute(&mut ecs, Command::Start).unwrap();
+    let a = recorder.execute(&mut ecs, Command::Spawn { name: "a \"quoted\"".to_string(), factory: "type-1".to_string() }).unwrap()[0];
+    let batch = recorder.execute(&mut ecs, Command::SpawnBatch { factory: "type-2".to_string(), count: 5 }).unwrap();
+    assert_eq!(batch.len(), 5);
+    for _ in 0..3 {
+        recorder.execute(&mut ecs, Command::Update(0.1)).unwrap();
+    }
+    let with = Command::SpawnWith { name: "with".to_string(), factory: "type-1".to_string(), args: vec![("team".to_string(), "red".to_string())] };
+    assert_eq!(recorder.execute(&mut ecs, with).unwrap().len(), 1);
+    let scene = Command::SpawnScene { file: "level.scene".to_string(), source: "type-1 scened\ntype-2\n".to_string() };
+    let members = recorder.execute(&mut ecs, scene).unwrap();
+    assert_eq!(members.len(), 2);
+    let scene = ecs.scene_of(&members[0]).unwrap();
+    let missing = Command::SpawnScene { file: "missing.scene".to_string(), source: "type-9".to_string() };
+    assert!(matches!(recorder.execute(&mut ecs, missing), Err(CommandError::Scene(_))));
+    recorder.execute(&mut ecs, Command::Set { target: a, path: "call2".to_string(), value: Value::Int(40) }).unwrap();
+    recorder.execute(&mut ecs, Command::AddComponent(a, "call-2".to_string())).unwrap();
+    recorder.execute(&mut ecs, Command::Destroy(batch[1])).unwrap();
+    recorder.execute(&mut ecs, Command::Rename(batch[2], "re\tnamed\u{1}".to_string())).unwrap();
+    assert_eq!(recorder.execute(&mut ecs, Command::Destroy(batch[1])), Err(CommandError::NotAlive(batch[1])));
+    assert!(matches!(
+        recorder.execute(&mut ecs, Command::Set { target: a, path: "nope".to_string(), value: Value::Int(1) }),
+        Err(CommandError::Field(ReflectError::UnknownField(_)))
+    ));
+    recorder.execute(&mut ecs, Command::UnloadScene(scene)).unwrap();
+    recorder.execute(&mut ecs, Command::Update(0.25)).unwrap();
+    assert_eq!(ecs.get_ref(&a).call2, 41);
+    assert_eq!(ecs.find("scened"), None);
+
+    let path = std::env::temp_dir().join(format!("simple_ecs_{}.rec", std::process::id()));
+    recorder.recording().save(&path).unwrap();
+    let loaded = Recording::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(&loaded, recorder.recording());
+    assert_eq!(loaded.frames(), 5);
+
+    let mut fresh = recorded_world();
+    assert_eq!(loaded.verify(&mut fresh), Ok(5));
+    assert_eq!(fresh.checksum(), ecs.checksum());
+    assert_eq!(fresh.find("re\tnamed\u{1}"), Some(batch[2]));
+    assert_eq!(fresh.objects.dt(), 0.25);
+    assert!(fresh.find("with").is_some());
+    let mut replayed = recorded_world();
+    assert_eq!(loaded.replay(&mut replayed), 5);
+    assert_eq!(replayed.checksum(), ecs.checksum());
+
+    // an edit the recording doesn't know about shows up at the next frame
+    let text = loaded.to_string().replace("int 40", "int 39");
+    let tampered = Recording::parse(&text).unwrap();
+    assert!(matches!(tampered.verify(&mut recorded_world()), Err(ReplayError::Diverged { frame: 5, .. })));
+    let mut other = recorded_world();
+    other.spawn("extra", "type-1");
+    assert!(matches!(loaded.verify(&mut other), Err(ReplayError::Diverged { frame: 0, .. })));
+
+    assert_eq!(Recording::parse("begin 0\njump 3\n"), Err(ReplayError::Parse { line: 2, message: "unknown command 'jump'".to_string() }));
+    assert!(matches!(Recording::parse("begin 0\nspawn \"open"), Err(ReplayError::Parse { line: 2, .. })));
+    assert!(matches!(Recording::parse("start 00"), Err(ReplayError::Parse { line: 0, .. })));
+}
+
+#[test]
+fn move_entities_between_worlds() {
+    let world = |size: usize, components: &[&str]| {
+        let mut builder = EcsBuilder::new(size).with_name_index(NamePolicy::Reject);
+        for component in components {
+            builder = builder.define_component(component);
+        }
+        let factories = builder.build_systems::<Cell>()
+            .setup_factories()
+                .define_factory("type-1", Box::new(Factory1));
+        match components.contains(&"call-2") {
+            true => factories.define_factory("type-2", Box::new(Factory2)).finalize(),
+            false => factories.finalize(),
+        }
+    };
+    let mut main = world(4, &["call-1", "call-2"]);
+    // the same components at other flag positions
+    let mut staging = world(4, &["call-3", "call-2", "call-1"]);
+
+    let a = main.spawn("a", "type-1").unwrap();
+    let b = main.spawn("", "type-2").unwrap();
+    main.add_component(&b, "call-1");
+    main.get_mut(&b).call2 = 9;
+    // call3 stands in for a handle to another object
+    main.get_mut(&a).call3 = b as u128;
+    staging.spawn("blocker", "type-2");
+
+    let map = main.move_entities_to(&mut staging, &[a, b, a], |cell, map| {
+        if let Some(new) = map.get(&(cell.call3 as usize)) {
+            cell.call3 = new as u128;
+        }
+    }).unwrap();
+    assert_eq!(map.len(), 2);
+    let (new_a, new_b) = (map.get(&a).unwrap(), map.get(&b).unwrap());
+    assert!(!main.is_alive(&a) && !main.is_alive(&b));
+    assert_eq!(staging.find("a"), Some(new_a));
+    assert_eq!(staging.get_ref(&new_a).call3, new_b as u128);
+    assert_eq!(staging.get_ref(&new_b).call2, 9);
+    assert!(staging.has_component(&new_a, "call-1") && !staging.has_component(&new_a, "call-2"));
+    assert!(staging.has_component(&new_b, "call-1") && staging.has_component(&new_b, "call-2"));
+    assert_eq!(staging.name_of(&new_b), Some(""));
+
+    let back = staging.move_entity_to(&mut main, &new_b).unwrap();
+    assert!(main.has_component(&back, "call-2"));
+    assert_eq!(main.get_ref(&back).call2, 9);
+    assert_eq!(staging.move_entity_to(&mut main, &new_b), Err(TransferError::NotAlive(new_b)));
+
+    // nothing moves when any of the objects can't
+    let taken = main.spawn("a", "type-1").unwrap();
+    assert_eq!(main.move_entities_to(&mut staging, &[back, taken], |_, _| {}), Err(TransferError::NameTaken("a".to_string())));
+    assert!(main.is_alive(&back) && main.is_alive(&taken));
+    let mut plain = world(4, &["call-1"]);
+    assert_eq!(main.move_entity_to(&mut plain, &back), Err(TransferError::UnknownComponent("call-2".to_string())));
+    let mut small = world(1, &["call-1", "call-2"]);
+    small.spawn("", "type-1");
+    assert_eq!(main.move_entity_to(&mut small, &back), Err(TransferError::Full { needed: 1, free: 0 }));
+    assert!(main.is_alive(&back));
+}
+
+struct SceneFactory;
+impl<'a> Factory<'a, Cell> for SceneFactory {
+    fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
+        tools.add_component("call-1");
+        if let Some(calls) = tools.arg::<String>("calls").and_then(|c| c.parse().ok()) {
+            tools.edit().call1 = calls;
+        }
+    }
+
+    fn components(&self) -> Vec<NameTag> { vec![ NameTag::from_str("call-1") ] }
+}
+
+struct NeedsCalls;
+impl<'a> Factory<'a, Cell> for NeedsCalls {
+    fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
+        tools.edit().call2 = tools.arg::<String>("calls").expect("calls is required").parse().unwrap();
+    }
+
+    fn components(&self) -> Vec<NameTag> { Vec::new() }
+}
+
+#[test]
+fn scenes_load_and_unload() {
+    let mut ecs = EcsBuilder::new(6)
+            .with_name_index(NamePolicy::Reject)
+            .define_component("call-1")
+            .define_component("call-2")
+        .build_systems()
+        .setup_factories()
+            .define_factory("scene", Box::new(SceneFactory))
+            .define_factory("type-2", Box::new(Factory2))
+            .define_factory("needs", Box::new(NeedsCalls))
+        .finalize();
+
+    // factories only run to spawn, not to check that they exist
+    let needs = ecs.spawn_scene_from("needs.scene", "needs calls=2\nneeds calls=5\n").unwrap();
+    assert_eq!(ecs.unload_scene(needs), 2);
+
+    let level = ecs.spawn_scene_from("level.scene", "# level\nscene gate calls=3\n\ntype-2\n").unwrap();
+    let other = ecs.spawn_scene_from("other.scene", "scene keeper\n").unwrap();
+    assert_ne!(level, other);
+    let gate = ecs.find("gate").unwrap();
+    let keeper = ecs.find("keeper").unwrap();
+    assert_eq!(ecs.get_ref(&gate).call1, 3);
+    assert_eq!(ecs.scene_of(&gate), Some(level));
+    assert_eq!(ecs.scene_members(level).len(), 2);
+    let loose = ecs.spawn("loose", "type-2").unwrap();
+    assert_eq!(ecs.scene_of(&loose), None);
+    assert!(ecs.set_scene(&loose, Some(level)));
+
+    assert_eq!(ecs.unload_scene(level), 3);
+    assert!(!ecs.is_alive(&gate) && !ecs.is_alive(&loose));
+    assert!(ecs.is_alive(&keeper));
+    assert_eq!(ecs.scene_of(&keeper), Some(other));
+    assert_eq!(ecs.unload_scene(level), 0);
+
+    // reused slots don't inherit the scene of the object that had them
+    let fresh = ecs.spawn("fresh", "type-2").unwrap();
+    assert_eq!(ecs.scene_of(&fresh), None);
+
+    assert_eq!(ecs.spawn_scene_from("bad.scene", "scene\ndragon boss\n").unwrap_err(),
+        SceneError { file: "bad.scene".to_string(), line: 2, message: "unknown factory 'dragon'".to_string() });
+    assert_eq!(ecs.spawn_scene_from("bad.scene", "scene a b\n").unwrap_err().message, "expected 'key=value', found 'b'");
+    // nothing of a scene stays behind when one of its objects can't be spawned
+    let before = ecs.capacity().active;
+    let error = ecs.spawn_scene_from("clash.scene", "scene one\nscene keeper\n").unwrap_err();
+    assert_eq!((error.line, ecs.capacity().active), (2, before));
+    assert_eq!(ecs.find("one"), None);
+    assert!(ecs.spawn_scene("missing.scene").is_err());
+}
+
+fn spatial_world() -> Ecs<'static, Cell> {
+    EcsBuilder::new(8)
+            .with_growth(GrowthPolicy::Double)
+            .define_component("call-1")
+            .define_component("call-2")
+        .build_systems()
+            // counts the neighbours within 2 of every object, itself included
+            .define_system_fn(&["call-1"], |target, objects: &mut Objects<Cell>, _| {
+                let position = (objects.get_ref(target).call1 as f32, objects.get_ref(target).call2 as f32);
+                let near = objects.query_radius(position, 2.0).len();
+                objects.get_mut(target).call3 = near as u128;
+            })
+            .with_spatial_index("call-1", 2.0, |cell: &Cell| (cell.call1 as f32, cell.call2 as f32))
+        .setup_factories()
+            .define_factory("type-1", Box::new(Factory1))
+            .define_factory("type-2", Box::new(Factory2))
+        .finalize()
+}
+
+#[test]
+fn spatial_queries() {
+    let mut ecs = spatial_world();
+    let place = |ecs: &mut Ecs<Cell>, x: u128, y: u128| {
+        let target = ecs.spawn("", "type-1").unwrap();
+        ecs.get_mut(&target).call1 = x;
+        ecs.get_mut(&target).call2 = y;
+        target
+    };
+    let a = place(&mut ecs, 0, 0);
+    let b = place(&mut ecs, 1, 1);
+    let c = place(&mut ecs, 5, 0);
+    let far = place(&mut ecs, 40, 40);
+    let untracked = ecs.spawn("", "type-2").unwrap();
+
+    let mut near = vec![a, b];
+    near.sort_unstable();
+    assert_eq!(ecs.query_radius((0.0, 0.0), 1.5), near);
+    assert_eq!(ecs.query_aabb((1.0, 0.0), (5.0, 1.0)), { let mut v = vec![b, c]; v.sort_unstable(); v });
+    assert_eq!(ecs.query_nearest((4.0, 0.0), 2), vec![c, b]);
+    assert_eq!(ecs.query_nearest((100.0, 100.0), 1), vec![far]);
+    assert_eq!(ecs.query_nearest((0.0, 0.0), 10).len(), 4);
+
+    ecs.start();
+    ecs.update();
+    assert_eq!((ecs.get_ref(&a).call3, ecs.get_ref(&c).call3), (2, 1));
+
+    // moving, losing the component and being destroyed all reach the index
+    ecs.get_mut(&c).call1 = 1;
+    assert_eq!(ecs.query_radius((0.0, 0.0), 1.5).len(), 3);
+    ecs.remove_component(&b, "call-1");
+    ecs.destroy(&a);
+    assert_eq!(ecs.query_radius((0.0, 0.0), 1.5), vec![c]);
+    ecs.add_component(&untracked, "call-1");
+    assert_eq!(ecs.query_nearest((0.0, 0.0), 1), vec![untracked]);
+    ecs.add_component(&b, "call-1");
+
+    let mut random = Random(0x9E37_79B9_7F4A_7C15);
+    let mut placed = Vec::new();
+    for _ in 0..60 {
+        placed.push(place(&mut ecs, random.next(30) as u128, random.next(30) as u128));
+    }
+    for i in 0..20 {
+        ecs.destroy(&placed[i * 3]);
+    }
+    let positions: Vec<(ObjectIndex, (f32, f32))> = ecs.entities.active.iter()
+        .filter(|p| ecs.has_component(p, "call-1"))
+        .map(|p| (*p, (ecs.objects.pool[*p].call1 as f32, ecs.objects.pool[*p].call2 as f32)))
+        .collect();
+    let distance = |a: (f32, f32), b: (f32, f32)| (a.0 - b.0).powi(2) + (a.1 - b.1).powi(2);
+
+    for _ in 0..20 {
+        let point = (random.next(30) as f32 + 0.5, random.next(30) as f32 + 0.5);
+        let mut expected: Vec<ObjectIndex> = positions.iter()
+            .filter(|p| distance(p.1, point) <= 16.0)
+            .map(|p| p.0)
+            .collect();
+        expected.sort_unstable();
+        assert_eq!(ecs.query_radius(point, 4.0), expected);
+
+        let mut by_distance = positions.clone();
+        by_distance.sort_by(|a, b| distance(a.1, point).total_cmp(&distance(b.1, point)).then(a.0.cmp(&b.0)));
+        let nearest: Vec<ObjectIndex> = by_distance.iter().take(5).map(|p| p.0).collect();
+        assert_eq!(ecs.query_nearest(point, 5), nearest);
+    }
+
+    // configuration mistakes don't panic
+    let unknown = EcsBuilder::new(4)
+        .build_systems::<Cell>()
+            .with_spatial_index("position", 1.0, |cell: &Cell| (cell.call1 as f32, 0.0))
+        .setup_factories()
+        .try_finalize();
+    assert_eq!(unknown.err(), Some(FactoryError::UnknownSpatialComponent("position".to_string())));
+
+    let mut plain: Ecs<Cell> = EcsBuilder::new(4).build_systems().setup_factories().finalize();
+    plain.spawn("", "");
+    assert!(!plain.objects.has_spatial_index());
+    assert!(plain.query_radius((0.0, 0.0), 10.0).is_empty());
+    assert!(plain.query_nearest((0.0, 0.0), 1).is_empty());
+}
+
+struct Enemy;
+impl<'a> Factory<'a, Cell> for Enemy {
+    fn make_spawn(&mut self, tools: &mut BuildTools<Cell>) {
+        tools.add_component("call-1");
+        tools.add_component("enemy");
+    }
+
+    fn components(&self) -> Vec<NameTag> { vec![ NameTag::from_str("call-1"), NameTag::from_str("enemy") ] }
+}
+
+#[test]
+fn tags() {
+    let mut ecs = EcsBuilder::new(8)
+            .define_component("call-1")
+            .define_tag("enemy")
+            .define_component("call-2")
+            .define_tag("selectable")
+        .build_systems()
+            .define_system_fn(&["call-1", "enemy"], |target, objects: &mut Objects<Cell>, _| {
+                objects.get_mut(target).call1 += 1;
+            })
+        .setup_factories()
+            .define_factory("enemy", Box::new(Enemy))
+            .define_factory("type-1", Box::new(Factory1))
+        .finalize();
+
+    // tags take no room in the change detection table
+    assert_eq!(ecs.entities.stride, 2);
+    assert_eq!(ecs.entities.added.len(), 8 * 2);
+    assert!(ecs.components().get(&NameTag::from_str("enemy")).unwrap().is_tag());
+
+    let orc = ecs.spawn("orc", "enemy").unwrap();
+    let friend = ecs.spawn("friend", "type-1").unwrap();
+    assert!(ecs.has_component(&orc, "enemy"));
+    ecs.start();
+    ecs.update();
+    assert_eq!((ecs.get_ref(&orc).call1, ecs.get_ref(&friend).call1), (1, 0));
+
+    assert!(ecs.set_tag(&friend, "enemy", true));
+    assert!(!ecs.set_tag(&friend, "enemy", true));
+    assert!(ecs.set_tag(&orc, "enemy", false));
+    assert!(ecs.set_tag(&orc, "selectable", true));
+    ecs.update();
+    assert_eq!((ecs.get_ref(&orc).call1, ecs.get_ref(&friend).call1), (1, 1));
+
+    // data components and unknown names are not tags
+    assert!(!ecs.set_tag(&orc, "call-2", true));
+    assert!(!ecs.set_tag(&orc, "hidden", true));
+    assert!(!ecs.has_component(&orc, "call-2"));
+    assert!(ecs.add_component(&orc, "call-2"));
+    assert_eq!(ecs.destroy_all_matching(&["selectable"]), 1);
+    assert!(!ecs.is_alive(&orc));
 }
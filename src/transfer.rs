@@ -0,0 +1,135 @@
+use std::fmt;
+use std::collections::{ HashMap, HashSet };
+
+use super::{ Ecs, ObjectIndex, ComponentIndex, NamePolicy, Symbol, set_name, reserve_slots, destroy_batch };
+
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TransferError {
+    NotAlive(ObjectIndex),
+    // a moved object has a component the other world doesn't define
+    UnknownComponent(String),
+    // the other world rejects duplicate names
+    NameTaken(String),
+    Full { needed: usize, free: usize },
+}
+
+impl fmt::Display for TransferError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransferError::NotAlive(target) => write!(f, "object {} is not alive", target),
+            TransferError::UnknownComponent(name) => write!(f, "the other world has no component '{}'", name),
+            TransferError::NameTaken(name) => write!(f, "the other world already has an object named '{}'", name),
+            TransferError::Full { needed, free } => write!(f, "the other world has room for {} of {} objects", free, needed),
+        }
+    }
+}
+
+impl std::error::Error for TransferError {}
+
+
+// Handles of moved objects in the world they left mapped to their handles in the
+// world they moved to, in the order they were moved
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EntityMap {
+    pairs: Vec<(ObjectIndex, ObjectIndex)>,
+    index: HashMap<ObjectIndex, ObjectIndex>,
+}
+
+impl EntityMap {
+    pub fn get(&self, old: &ObjectIndex) -> Option<ObjectIndex> {
+        self.index.get(old).copied()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &(ObjectIndex, ObjectIndex)> {
+        self.pairs.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    fn insert(&mut self, old: ObjectIndex, new: ObjectIndex) {
+        self.pairs.push((old, new));
+        self.index.insert(old, new);
+    }
+}
+
+
+// Everything is checked before the first object moves, so on an error both worlds are
+// left as they were. Components are matched by name, their flag positions may differ.
+pub(crate) fn move_entities<T: Default, F>(
+    from: &mut Ecs<T>,
+    to: &mut Ecs<T>,
+    targets: &[ObjectIndex],
+    mut remap: F,
+) -> Result<EntityMap, TransferError>
+where F: FnMut(&mut T, &EntityMap) {
+
+    let mut seen = HashSet::new();
+    let targets: Vec<ObjectIndex> = targets.iter().copied().filter(|t| seen.insert(*t)).collect();
+
+    // flag position in the other world of every component of this one
+    let positions: Vec<Option<ComponentIndex>> = (0..from.entities.columns.len())
+        .map(|c| from.component_refs.list().iter().find(|r| *r.index() == c)
+            .and_then(|r| to.component_refs.get(r.name()))
+            .map(|r| *r.index()))
+        .collect();
+
+    let reject = to.objects.names.as_ref().is_some_and(|n| n.policy == NamePolicy::Reject);
+    let mut names = HashSet::new();
+    for target in &targets {
+        if !from.entities.is_alive(target) {
+            return Err(TransferError::NotAlive(*target));
+        }
+        let flags = &from.entities.pool[*target];
+        if let Some(c) = (0..positions.len()).find(|c| flags.bit(*c) && positions[*c].is_none()) {
+            let component = from.component_refs.list().iter().find(|r| *r.index() == c).unwrap();
+            return Err(TransferError::UnknownComponent(component.name().to_string()));
+        }
+        let name = from.objects.symbols.resolve(from.objects.active[from.entities.sparse[*target]].1);
+        if reject && !name.is_empty() && (!to.objects.name_available(name) || !names.insert(name)) {
+            return Err(TransferError::NameTaken(name.to_string()));
+        }
+    }
+    reserve_slots(targets.len(), &mut to.entities, &mut to.objects);
+    if to.entities.free.len() < targets.len() {
+        return Err(TransferError::Full { needed: targets.len(), free: to.entities.free.len() });
+    }
+
+    let mut map = EntityMap::default();
+    for target in &targets {
+        let pointer = to.entities.free.pop().unwrap();
+        let name = from.objects.symbols.resolve(from.objects.active[from.entities.sparse[*target]].1);
+        let name = if name.is_empty() { Symbol::EMPTY } else { to.objects.symbols.acquire(name) };
+
+        to.entities.activate(pointer);
+        to.objects.active.push((pointer, Symbol::EMPTY));
+        to.objects.pool[pointer] = std::mem::take(&mut from.objects.pool[*target]);
+        set_name(pointer, name, &to.entities, &mut to.objects);
+
+        for (c, position) in positions.iter().enumerate() {
+            if let Some(position) = position {
+                if from.entities.pool[*target].bit(c) {
+                    to.entities.pool[pointer].set_bit(*position, true);
+                }
+            }
+        }
+        to.objects.changed[pointer] = to.objects.tick;
+        to.entities.stamp_added(pointer, to.objects.tick);
+        if let Some(spatial) = &mut to.objects.spatial {
+            spatial.track(pointer, &to.entities.pool[pointer]);
+        }
+        map.insert(*target, pointer);
+    }
+    destroy_batch(&targets, &mut from.entities, &mut from.objects);
+
+    for (_, pointer) in map.pairs.iter() {
+        remap(&mut to.objects.pool[*pointer], &map);
+    }
+    Ok(map)
+}
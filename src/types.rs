@@ -1,43 +1,202 @@
+use std::collections::HashMap;
 
 use super::ComponentIndex;
 
-#[derive(Default, Clone, PartialEq)]
-pub struct NameTag (pub(crate) [u8; 16]);
+// Names as given by the user, kept as they are without truncating or padding
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct NameTag (pub(crate) String);
 
 impl NameTag {
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Self {
         Self::from_string(s.to_string())
     }
 
     pub fn from_string(s: String) -> Self {
-        let mut b = s.into_bytes();
-        b.resize(16, 0);
-        NameTag([
-            b[0], b[1], b[2], b[3], 
-            b[4], b[5], b[6], b[7],
-            b[8], b[9], b[10], b[11], 
-            b[12], b[13], b[14], b[15]
-            ])
+        NameTag(s)
     }
 
+    pub fn as_str(&self) -> &str { &self.0 }
+
+    #[allow(clippy::inherent_to_string)]
     pub fn to_string(&self) -> String {
-        let mut v = Vec::<u8>::new();
-        v.extend_from_slice(&self.0);
-        String::from_utf8(v).unwrap_or(String::new())
+        self.0.clone()
+    }
+
+    // name of a typed component key, see ComponentKey
+    pub fn of<K: ComponentKey>() -> Self {
+        NameTag::from_str(K::NAME)
     }
 }
 
 
-pub struct ComponentRefs(pub(crate) Vec<ComponentRef>);
+// Marker types that refer to a component by type instead of by string, so a 
+// misspelled component fails to compile. The name is only kept for lookups,
+// serialization and debugging. Usually generated with the components! macro.
+pub trait ComponentKey {
+    const NAME: &'static str;
+}
+
+//   components! {
+//       pub Position = "position";
+//       pub Velocity = "velocity";
+//   }
+#[macro_export]
+macro_rules! components {
+    ($($vis:vis $key:ident = $name:expr;)*) => {
+        $(
+            #[derive(Debug, Default, Clone, Copy, PartialEq)]
+            $vis struct $key;
+
+            impl $crate::ComponentKey for $key {
+                const NAME: &'static str = $name;
+            }
+        )*
+    };
+}
+
+
+// Compact id of an interned name, only meaningful for the Symbols table it came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(pub(crate) u32);
+
+impl Symbol {
+    // the empty name, used for unnamed objects
+    pub const EMPTY: Symbol = Symbol(0);
+}
+
+// Interns component, factory and object names, every distinct name gets its own 
+// Symbol. Component and factory names keep theirs for as long as the table lives,
+// object names are counted and their Symbol is freed for reuse once no object has it.
+pub struct Symbols {
+    names: Vec<String>,
+    lookup: HashMap<String, Symbol>,
+    uses: Vec<Option<usize>>, // objects using the name, None for names that are never freed
+    free: Vec<Symbol>,
+}
+
+impl Default for Symbols {
+    fn default() -> Self { Self::new() }
+}
+
+impl Symbols {
+    pub fn new() -> Self {
+        let mut symbols = Symbols { names: Vec::new(), lookup: HashMap::new(), uses: Vec::new(), free: Vec::new() };
+        symbols.intern("");
+        symbols
+    }
+
+    pub fn intern(&mut self, name: &str) -> Symbol {
+        let symbol = self.insert(name, None);
+        self.uses[symbol.0 as usize] = None;
+        symbol
+    }
+
+    // interns an object name, to be given back with release when the object drops it
+    pub(crate) fn acquire(&mut self, name: &str) -> Symbol {
+        let symbol = self.insert(name, Some(0));
+        if let Some(uses) = &mut self.uses[symbol.0 as usize] {
+            *uses += 1;
+        }
+        symbol
+    }
+
+    pub(crate) fn release(&mut self, symbol: Symbol) {
+        let slot = symbol.0 as usize;
+        if let Some(uses) = &mut self.uses[slot] {
+            *uses -= 1;
+            if *uses == 0 {
+                self.lookup.remove(&self.names[slot]);
+                self.names[slot].clear();
+                self.uses[slot] = None;
+                self.free.push(symbol);
+            }
+        }
+    }
+
+    fn insert(&mut self, name: &str, uses: Option<usize>) -> Symbol {
+        if let Some(symbol) = self.lookup.get(name) {
+            return *symbol;
+        }
+        let symbol = match self.free.pop() {
+            Some(symbol) => {
+                self.names[symbol.0 as usize] = name.to_string();
+                self.uses[symbol.0 as usize] = uses;
+                symbol
+            },
+            None => {
+                self.names.push(name.to_string());
+                self.uses.push(uses);
+                Symbol(self.names.len() as u32 - 1)
+            },
+        };
+        self.lookup.insert(name.to_string(), symbol);
+        symbol
+    }
+
+    // looks a name up without interning it
+    pub fn get(&self, name: &str) -> Option<Symbol> {
+        self.lookup.get(name).copied()
+    }
+
+    pub fn resolve(&self, symbol: Symbol) -> &str {
+        &self.names[symbol.0 as usize]
+    }
+
+    // names currently interned
+    pub fn len(&self) -> usize { self.lookup.len() }
+    pub fn is_empty(&self) -> bool { self.lookup.is_empty() }
+}
+
+
+pub struct ComponentRefs {
+    list: Vec<ComponentRef>,
+    names: HashMap<String, ComponentIndex>,
+    symbols: HashMap<Symbol, ComponentIndex>,
+}
 
 impl ComponentRefs {
 
+    pub(crate) fn new() -> Self {
+        ComponentRefs { list: Vec::new(), names: HashMap::new(), symbols: HashMap::new() }
+    }
+
+    // a name defined twice keeps resolving to its first definition
+    pub(crate) fn push(&mut self, name: &str, symbol: Symbol, tag: bool) {
+        let index = self.list.len();
+        self.names.entry(name.to_string()).or_insert(index);
+        self.symbols.entry(symbol).or_insert(index);
+        self.list.push(ComponentRef::new(index, name, symbol, tag));
+    }
+
     pub fn get(&self, tag: &NameTag) -> Option<&ComponentRef> {
-        self.0.iter().find(|c| c.name == *tag)
+        self.names.get(tag.as_str()).map(|i| &self.list[*i])
+    }
+
+    pub fn of<K: ComponentKey>(&self) -> Option<&ComponentRef> {
+        self.names.get(K::NAME).map(|i| &self.list[*i])
+    }
+
+    pub fn get_symbol(&self, symbol: Symbol) -> Option<&ComponentRef> {
+        self.symbols.get(&symbol).map(|i| &self.list[*i])
     }
 
     pub fn list(&self) -> &Vec<ComponentRef> {
-        &self.0
+        &self.list
+    }
+
+    // flags for the given component names, None when one of them is not defined
+    pub fn flags_of(&self, names: &[&str]) -> Option<BitFlags> {
+        let mut flags = BitFlags::default();
+        for name in names {
+            flags.set_bit(*self.get(&NameTag::from_str(name))?.index(), true);
+        }
+        Some(flags)
+    }
+
+    // components whose bit is set in the given flags
+    pub fn decode(&self, flags: &BitFlags) -> Vec<&ComponentRef> {
+        self.list.iter().filter(|c| flags.0 & (1 << c.index) != 0).collect()
     }
 }
 
@@ -45,18 +204,24 @@ impl ComponentRefs {
 pub struct ComponentRef {
     index: ComponentIndex,
     name: NameTag,
+    symbol: Symbol,
+    tag: bool, // only a bit in the signature, see EcsBuilder::define_tag
 }
 
 impl ComponentRef {
-    pub(crate) fn new(index: ComponentIndex, name: &str) -> Self {
+    pub(crate) fn new(index: ComponentIndex, name: &str, symbol: Symbol, tag: bool) -> Self {
         ComponentRef {
             index,
             name: NameTag::from_str(name),
+            symbol,
+            tag,
         }
     }
 
     pub fn index(&self) -> &ComponentIndex { &self.index }
     pub fn name(&self) -> &NameTag { &self.name }
+    pub fn symbol(&self) -> Symbol { self.symbol }
+    pub fn is_tag(&self) -> bool { self.tag }
 }
 
 
@@ -64,6 +229,11 @@ impl ComponentRef {
 pub struct BitFlags(pub(crate) u32);
 
 impl BitFlags {
+    // the first `count` bits set
+    pub fn all(count: usize) -> Self {
+        BitFlags(if count >= 32 { u32::MAX } else { (1 << count) - 1 })
+    }
+
     pub fn reset(&mut self) { self.0 = 0; }
 
     pub fn set_bit(&mut self, at_index: ComponentIndex, to: bool) {
@@ -73,6 +243,10 @@ impl BitFlags {
         }
     }
 
+    pub fn bit(&self, at_index: ComponentIndex) -> bool { self.0 & (1 << at_index) != 0 }
+
+    pub fn contains(&self, other: &BitFlags) -> bool { self.0 & other.0 == other.0 }
+
     pub fn enable_bits(&mut self, bits: u32) { self.0 |= bits }
     pub fn disable_bits(&mut self, bits: u32) { self.0 &= !bits }
 }